@@ -0,0 +1,68 @@
+//! Compares preparing the query once for many small files (as `run` does with a `Matcher`) with preparing it
+//! again for every file, which recompiles the regular expression each time.
+//!
+//! Run with `cargo bench`.
+use std::time::{Duration, Instant};
+
+use mgrep::Matcher;
+
+// Runs `f` a few times and keeps the fastest run, to smooth out noise.
+fn fastest<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
+    let mut best = None;
+    let mut result = None;
+
+    for _ in 0..5 {
+        let start = Instant::now();
+        result = Some(f());
+        let elapsed = start.elapsed();
+        best = Some(best.map_or(elapsed, |best: Duration| best.min(elapsed)));
+    }
+
+    (result.unwrap(), best.unwrap())
+}
+
+const FILES: usize = 1000;
+
+fn main() {
+    // Small files, like the sources of a project: a few dozen lines each.
+    let files: Vec<String> = (0..FILES)
+        .map(|file| {
+            (0..40)
+                .map(|line| {
+                    if line % 10 == 0 {
+                        format!("fn handler_{file}_{line}(request: Request) -> Response {{\n")
+                    } else {
+                        format!("    let value_{line} = compute({file}, {line});\n")
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let query = r"fn \w+\(";
+
+    let (per_file, per_file_time) = fastest(|| {
+        files
+            .iter()
+            .map(|contents| {
+                let matcher = Matcher::new(query, true, true, false).unwrap();
+                mgrep::search(&matcher, false, contents).len()
+            })
+            .sum::<usize>()
+    });
+    let (once, once_time) = fastest(|| {
+        let matcher = Matcher::new(query, true, true, false).unwrap();
+        files
+            .iter()
+            .map(|contents| mgrep::search(&matcher, false, contents).len())
+            .sum::<usize>()
+    });
+
+    assert_eq!(per_file, once);
+    println!("files: {FILES}, matches: {once}");
+    println!("compiled for every file: {per_file_time:?}");
+    println!("compiled once:           {once_time:?}");
+    println!(
+        "speedup:                 {:.1}x",
+        per_file_time.as_secs_f64() / once_time.as_secs_f64()
+    );
+}
@@ -0,0 +1,73 @@
+//! Compares searching a file of about 300 MB by reading it (the default) with memory-mapping it (`--mmap`).
+//!
+//! Run with `cargo bench --bench mmap`. The file is written to the temporary directory, and removed after.
+use std::{
+    env, fs,
+    time::{Duration, Instant},
+};
+
+use mgrep::{Config, InputType};
+
+// Runs `f` a few times and keeps the fastest run, to smooth out noise.
+fn fastest<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
+    let mut best = None;
+    let mut result = None;
+
+    for _ in 0..5 {
+        let start = Instant::now();
+        result = Some(f());
+        let elapsed = start.elapsed();
+        best = Some(best.map_or(elapsed, |best: Duration| best.min(elapsed)));
+    }
+
+    (result.unwrap(), best.unwrap())
+}
+
+const LINES: usize = 5_000_000;
+
+fn main() {
+    // About 60 bytes per line, so 5 million lines make up roughly 300 MB.
+    let dir = env::temp_dir().join(format!("mgrep-bench-mmap-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("big.log");
+    let contents: String = (0..LINES)
+        .map(|index| {
+            if index % 100 == 0 {
+                format!("{index}: The Quick Brown Fox jumps over the lazy dog\n")
+            } else {
+                format!("{index}: lorem ipsum dolor sit amet, consectetur adipiscing elit\n")
+            }
+        })
+        .collect();
+    fs::write(&path, &contents).unwrap();
+    let size = contents.len();
+    drop(contents);
+
+    // Only the count is written, so the time is spent searching rather than printing.
+    let search = |mmap: bool| {
+        let config = Config::new(
+            "Quick Brown".to_string(),
+            InputType::FilePath(path.display().to_string()),
+        )
+        .with_count(true)
+        .with_mmap(mmap)
+        .with_output(Some(dir.join("count.txt").display().to_string()));
+        mgrep::run(config).unwrap().count
+    };
+
+    let (read, read_time) = fastest(|| search(false));
+    let (mapped, mapped_time) = fastest(|| search(true));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(read, mapped);
+    println!(
+        "lines: {LINES}, size: {} MB, matches: {mapped}",
+        size / 1_000_000
+    );
+    println!("read:    {read_time:?}");
+    println!("--mmap:  {mapped_time:?}");
+    println!(
+        "speedup: {:.1}x",
+        read_time.as_secs_f64() / mapped_time.as_secs_f64()
+    );
+}
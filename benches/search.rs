@@ -0,0 +1,59 @@
+//! Compares the case-insensitive line filter, which compares the lines in place, with the naive approach of
+//! lowercasing every line (and the query, again) before calling `contains`, on about 100 MB of text.
+//!
+//! Run with `cargo bench`.
+use std::time::{Duration, Instant};
+
+// Runs `f` a few times and keeps the fastest run, to smooth out noise.
+fn fastest<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
+    let mut best = None;
+    let mut result = None;
+
+    for _ in 0..5 {
+        let start = Instant::now();
+        result = Some(f());
+        let elapsed = start.elapsed();
+        best = Some(best.map_or(elapsed, |best: Duration| best.min(elapsed)));
+    }
+
+    (result.unwrap(), best.unwrap())
+}
+
+const LINES: usize = 1_700_000;
+
+fn main() {
+    // About 60 bytes per line, so 1.7 million lines make up roughly 100 MB.
+    let contents: String = (0..LINES)
+        .map(|index| {
+            if index % 100 == 0 {
+                format!("{index}: The Quick Brown Fox jumps over the lazy dog\n")
+            } else {
+                format!("{index}: lorem ipsum dolor sit amet, consectetur adipiscing elit\n")
+            }
+        })
+        .collect();
+    let query = "quick brown";
+
+    let (naive, naive_time) = fastest(|| {
+        contents
+            .lines()
+            .filter(|line| line.to_lowercase().contains(&query.to_lowercase()))
+            .count()
+    });
+    let (current, current_time) = fastest(|| {
+        let matcher = mgrep::Matcher::new(query, true, false, false).unwrap();
+        mgrep::search(&matcher, false, &contents).len()
+    });
+
+    assert_eq!(naive, current);
+    println!(
+        "lines: {LINES}, size: {} MB, matches: {current}",
+        contents.len() / 1_000_000
+    );
+    println!("lowercase every line: {naive_time:?}");
+    println!("mgrep::search:        {current_time:?}");
+    println!(
+        "speedup:              {:.1}x",
+        naive_time.as_secs_f64() / current_time.as_secs_f64()
+    );
+}
@@ -0,0 +1,114 @@
+/*
+    error: Std error trait;
+    fmt: Error messages;
+    io: I/O errors;
+    path: Paths of unreadable files;
+    time: Timeouts
+*/
+use std::{error::Error, fmt, io, path::PathBuf, time::Duration};
+
+/// Everything that can stop `Config::build` or `run`, so library users can tell the failures apart.
+///
+/// Files that can't be read during a recursive search don't stop it, and are only reported in
+/// `Summary::errors` instead.
+#[derive(Debug)]
+pub enum MgrepError {
+    /// No query was given (nor a pattern file with `-f`).
+    MissingQuery,
+    /// The query is empty or only whitespace, which would select every line, without `--allow-empty`.
+    EmptyQuery,
+    /// A file (the searched file, or the pattern file of `-f`) couldn't be read.
+    FileRead(io::Error, PathBuf),
+    /// The path to search is a directory, but `-r` wasn't given to search it recursively.
+    IsDirectory(PathBuf),
+    /// The file given to `--output` couldn't be created.
+    OutputFile(io::Error, PathBuf),
+    /// The query (or one of the patterns) isn't a valid regular expression.
+    InvalidRegex(String, regex::Error),
+    /// The value of `--highlight-color` isn't a known color.
+    UnknownColor(String),
+    /// The value of `--encoding` isn't a known encoding.
+    UnknownEncoding(String),
+    /// The value of a flag is invalid, e.g. `--sort=size` or `-m x`.
+    InvalidValue {
+        flag: String,
+        value: String,
+        expected: String,
+    },
+    /// The arguments can't be combined or are out of order, e.g. `-l` with `-L`.
+    Usage(String),
+    /// Reading stdin or writing the results failed.
+    Io(io::Error),
+    /// The search took longer than the `--timeout` it was given.
+    Timeout(Duration),
+}
+
+impl fmt::Display for MgrepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MgrepError::MissingQuery => write!(f, "Didn't get a query string"),
+            MgrepError::EmptyQuery => write!(
+                f,
+                "query must not be empty (it would match every line), use --allow-empty if that's intended"
+            ),
+            MgrepError::FileRead(err, path) => write!(f, "Can't read '{}': {err}", path.display()),
+            MgrepError::IsDirectory(path) => write!(
+                f,
+                "{}: is a directory (use -r to search recursively)",
+                path.display()
+            ),
+            MgrepError::OutputFile(err, path) => {
+                write!(f, "Can't create output file '{}': {err}", path.display())
+            }
+            MgrepError::InvalidRegex(query, err) => {
+                write!(f, "Invalid regular expression '{query}': {err}")
+            }
+            MgrepError::UnknownColor(value) => write!(
+                f,
+                "Invalid value for --highlight-color: '{value}' \
+                (expected red, green, yellow, blue, magenta, cyan or bold)"
+            ),
+            MgrepError::UnknownEncoding(label) => write!(
+                f,
+                "Unknown encoding '{label}' (e.g. utf-8, latin1, utf-16le, shift_jis)"
+            ),
+            MgrepError::InvalidValue {
+                flag,
+                value,
+                expected,
+            } => write!(f, "Invalid value for {flag}: '{value}' (expected {expected})"),
+            MgrepError::Usage(message) => write!(f, "{message}"),
+            MgrepError::Io(err) => write!(f, "{err}"),
+            MgrepError::Timeout(timeout) => write!(f, "Search timed out after {timeout:?}"),
+        }
+    }
+}
+
+impl Error for MgrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MgrepError::FileRead(err, _) | MgrepError::OutputFile(err, _) | MgrepError::Io(err) => {
+                Some(err)
+            }
+            MgrepError::InvalidRegex(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MgrepError {
+    fn from(err: io::Error) -> MgrepError {
+        MgrepError::Io(err)
+    }
+}
+
+impl MgrepError {
+    // The invalid `value` of `flag`, described by what was `expected` instead.
+    pub(crate) fn invalid_value(flag: &str, value: &str, expected: &str) -> MgrepError {
+        MgrepError::InvalidValue {
+            flag: flag.to_string(),
+            value: value.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+}
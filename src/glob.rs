@@ -0,0 +1,203 @@
+/*
+    fs: Directory listing;
+    path: Path manipulation
+*/
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Checks whether `name` matches the glob `pattern` as a whole.
+///
+/// # Arguments
+/// - `pattern`: A glob where `*` matches any run of characters, `?` matches any single character, and
+///   `[abc]`, `[a-z]` or `[!abc]` match one character of (or not of) a set. Anything else matches itself.
+/// - `name`: The text to match, usually a file name.
+///
+/// # Returns
+/// - `true` if the whole `name` matches the `pattern`.
+/// - `false` otherwise.
+///
+/// # Note
+/// - An unclosed `[` is matched literally.
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*`: the pattern right after it, and the name it may swallow up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, len)) = match_class(&pattern[p..], name[n]) {
+                    if matched {
+                        p += len;
+                        n += 1;
+                        continue;
+                    }
+                } else if name[n] == '[' {
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+            }
+            Some(&c) if c == name[n] => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        // Mismatch: let the last `*` swallow one more character, if there's one.
+        match backtrack {
+            Some((after_star, swallowed)) => {
+                p = after_star;
+                n = swallowed + 1;
+                backtrack = Some((after_star, n));
+            }
+            None => return false,
+        }
+    }
+
+    // Trailing stars match the empty rest of the name.
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Expands a glob given as a path argument (e.g. `*.txt` or `logs/app-?.log`) into the files it names, like a
+/// Unix shell does (`cmd.exe` on Windows passes it as is).
+///
+/// # Arguments
+/// - `pattern`: A path whose file name may contain the wildcards of `matches`.
+///
+/// # Returns
+/// The matching regular files, sorted, or nothing if `pattern` has no wildcard or matches no file.
+///
+/// # Note
+/// - Only the file name is expanded: wildcards in the directories (e.g. `*/lib.rs`) match nothing.
+/// - As in shells, hidden files (starting with `.`) are only matched by a pattern starting with `.`.
+pub(crate) fn expand(pattern: &str) -> Vec<PathBuf> {
+    let path = Path::new(pattern);
+    let (Some(name), Some(dir)) = (
+        path.file_name().and_then(|name| name.to_str()),
+        path.parent(),
+    ) else {
+        return Vec::new();
+    };
+    if !has_wildcard(name) || has_wildcard(&dir.to_string_lossy()) {
+        return Vec::new();
+    }
+
+    // A bare file name is looked for in the current directory, and printed without a `./` prefix.
+    let entries = match fs::read_dir(if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+
+            (name.starts_with('.') || !file_name.starts_with('.'))
+                && matches(name, &file_name)
+                && entry.path().is_file()
+        })
+        .map(|entry| dir.join(entry.file_name()))
+        .collect();
+    files.sort();
+
+    files
+}
+
+// Whether `text` contains a character with a special meaning in a glob.
+fn has_wildcard(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+// Matches `c` against the character class at the start of `pattern` (which starts with `[`).
+// Returns whether it matched and the length of the class, or `None` if the class isn't closed.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+
+    // A `]` right after the opening (or the negation) is part of the set.
+    while first || pattern.get(i) != Some(&']') {
+        let start = *pattern.get(i)?;
+        first = false;
+
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            matched |= (start..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+
+    Some((matched != negated, i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests the wildcards, the character classes and the literal characters.
+    #[test]
+    fn glob_matching() {
+        assert!(matches("*.rs", "lib.rs"));
+        assert!(matches("*_test.rs", "walk_test.rs"));
+        assert!(!matches("*.rs", "lib.rs.bak"));
+        assert!(matches("?.txt", "a.txt"));
+        assert!(!matches("?.txt", "ab.txt"));
+        assert!(matches("file[0-9].log", "file7.log"));
+        assert!(!matches("file[!0-9].log", "file7.log"));
+        assert!(matches("*a*b*", "xxaxxbxx"));
+        assert!(matches("[a.txt", "[a.txt"));
+        assert!(matches("*", ""));
+    }
+
+    // Tests that a file name glob is expanded to the matching files of its directory, hidden ones aside.
+    #[test]
+    fn glob_expansion() {
+        let root = std::env::temp_dir().join(format!("mgrep-glob-{}", std::process::id()));
+        fs::create_dir_all(root.join("sub.rs")).unwrap();
+        for name in ["b.rs", "a.rs", "notes.txt", ".hidden.rs"] {
+            fs::write(root.join(name), name).unwrap();
+        }
+        let pattern = |glob: &str| root.join(glob).to_str().unwrap().to_string();
+
+        assert_eq!(
+            vec![root.join("a.rs"), root.join("b.rs")],
+            expand(&pattern("*.rs"))
+        );
+        assert_eq!(vec![root.join(".hidden.rs")], expand(&pattern(".*.rs")));
+        assert!(expand(&pattern("*.md")).is_empty());
+        assert!(expand(&pattern("notes.txt")).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+/*
+    borrow: Path names;
+    fs: Ignore files;
+    path: Path manipulation;
+    glob: Path segment matching
+*/
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::glob;
+
+/// The ignore files read in each directory of a recursive search, in order: the patterns of the later ones win.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".mgrepignore"];
+
+// One pattern of an ignore file.
+struct Rule {
+    // The directory of the ignore file: the pattern only applies to the paths under it.
+    base: PathBuf,
+    // The `/`-separated segments of the pattern, each a glob, or `**` for any number of directories.
+    segments: Vec<String>,
+    // `!pattern`: the matching paths are kept, even if an earlier pattern left them out.
+    negated: bool,
+    // `pattern/`: only directories match.
+    dir_only: bool,
+}
+
+/// The patterns of the ignore files (see `IGNORE_FILES`) found from the root of a walk down to the directory
+/// being walked, like git's `.gitignore` files: each applies to the subtree of its directory.
+///
+/// # Note
+/// - Blank lines and lines starting with `#` are skipped, and `\#` or `\!` escape a leading `#` or `!`.
+/// - A pattern without a `/` (other than a trailing one) matches a name at any depth, e.g. `*.log`. Otherwise it
+///   matches the path from the ignore file's directory, e.g. `/build` or `docs/*.html`, where `**` matches any
+///   number of directories.
+/// - Ignore files above the root of the walk (e.g. of the enclosing repository) aren't read.
+#[derive(Default)]
+pub(crate) struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// Adds the patterns of the ignore files in `dir`, when entering it.
+    ///
+    /// # Returns
+    /// The number of patterns before, to give back to `leave` once the walk is done with `dir`.
+    pub(crate) fn enter(&mut self, dir: &Path) -> usize {
+        let len = self.rules.len();
+
+        // A missing or unreadable ignore file simply has no patterns.
+        for name in IGNORE_FILES {
+            if let Ok(contents) = fs::read_to_string(dir.join(name)) {
+                self.rules
+                    .extend(contents.lines().filter_map(|line| parse(dir, line)));
+            }
+        }
+
+        len
+    }
+
+    /// Removes the patterns added by `enter`, whose `len` it returned, when leaving their directory.
+    pub(crate) fn leave(&mut self, len: usize) {
+        self.rules.truncate(len);
+    }
+
+    /// Checks whether `path` (a directory if `is_dir`) is left out: the last pattern matching it decides.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.matches(path))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+impl Rule {
+    // Whether `path` matches the pattern, relative to the ignore file's directory.
+    fn matches(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let names: Vec<Cow<str>> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+
+        matches_segments(&self.segments, &names)
+    }
+}
+
+// Parses a `line` of the ignore file in `dir`, or `None` for a blank line or a comment.
+fn parse(dir: &Path, line: &str) -> Option<Rule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let line = line.strip_prefix('\\').unwrap_or(line);
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+
+    if line.trim_matches('/').is_empty() {
+        return None;
+    }
+
+    // Without a `/`, the pattern is a name, which can be at any depth.
+    let mut segments = Vec::new();
+    if !line.contains('/') {
+        segments.push("**".to_string());
+    }
+    segments.extend(
+        line.split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(str::to_string),
+    );
+
+    Some(Rule {
+        base: dir.to_path_buf(),
+        segments,
+        negated,
+        dir_only,
+    })
+}
+
+// Whether the path `names` match the pattern `segments` one by one, `**` matching any number of them.
+fn matches_segments(segments: &[String], names: &[Cow<str>]) -> bool {
+    match segments.split_first() {
+        None => names.is_empty(),
+        Some((segment, rest)) if segment == "**" => {
+            (0..=names.len()).any(|skip| matches_segments(rest, &names[skip..]))
+        }
+        Some((segment, rest)) => names.split_first().is_some_and(|(name, names)| {
+            glob::matches(segment, name) && matches_segments(rest, names)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests that names match at any depth, paths from the ignore file's directory, and that the last
+    // matching pattern wins, so `!` keeps a file an earlier pattern left out.
+    #[test]
+    fn ignore_patterns() {
+        let base = Path::new("/repo");
+        let rules = Rules {
+            rules: [
+                "# build output",
+                "",
+                "*.log",
+                "!keep.log",
+                "target/",
+                "/docs/*.html",
+                "a/**/z.txt",
+                "\\#notes",
+            ]
+            .iter()
+            .filter_map(|line| parse(base, line))
+            .collect(),
+        };
+        let ignored = |path: &str, is_dir| rules.is_ignored(&base.join(path), is_dir);
+
+        assert!(ignored("app.log", false));
+        assert!(ignored("src/deep/app.log", false));
+        assert!(!ignored("keep.log", false));
+        assert!(ignored("target", true));
+        assert!(ignored("nested/target", true));
+        assert!(!ignored("target", false));
+        assert!(ignored("docs/index.html", false));
+        assert!(!ignored("src/docs/index.html", false));
+        assert!(ignored("a/z.txt", false));
+        assert!(ignored("a/b/c/z.txt", false));
+        assert!(ignored("#notes", false));
+        assert!(!ignored("src/main.rs", false));
+        assert!(!rules.is_ignored(Path::new("/elsewhere/app.log"), false));
+    }
+}
@@ -1,32 +1,596 @@
 /*
+    borrow: Borrowed or combined query;
+    collections: Buffered context lines;
     env: Terminal;
-    error: Std error trait;
+    ffi: Environment variable values;
     fs: Filesystem manipulation operations;
-    io: I/O functionality
+    io: I/O functionality;
+    path: Path inspection;
+    process: Preprocessor commands;
+    sync: Early exit of the parallel search, and results of a search with a timeout;
+    thread: Available parallelism, and searches with a timeout;
+    time: Progress reports and timeouts;
+    encoding_rs: Non-UTF-8 files;
+    flate2: Gzip-compressed files;
+    memchr: Case-insensitive literal search;
+    memmap2: Memory-mapped files;
+    rayon: Parallel multi-file search;
+    regex: Regular expression matching;
+    unicode_normalization: Normalized queries
 */
 use std::{
+    borrow::Cow,
+    collections::VecDeque,
     env,
-    error::Error,
-    fs,
-    io::{self, Read},
+    ffi::OsString,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+    panic,
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
+use encoding_rs::{Encoding, UTF_8};
+use flate2::read::MultiGzDecoder;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use unicode_normalization::UnicodeNormalization;
+
+mod error;
+mod glob;
+mod ignore;
+mod normalize;
+mod progress;
+mod walk;
+
+pub use error::MgrepError;
+use normalize::Normalized;
+
 // enum for Config.input
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 pub enum InputType {
     FilePath(String),
+    // The files named by a glob that the shell didn't expand (e.g. `*.txt` with `cmd.exe`), expanded by mgrep.
+    FilePaths(Vec<String>),
     LiteralInput(String),
+    // Stdin, read and searched line by line as it arrives (`--line-buffered`).
+    Stdin,
+}
+
+// When to highlight the matches with ANSI escape codes.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ColorChoice {
+    // Only when stdout is a terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Parses the value of `--color`.
+    ///
+    /// # Returns
+    /// - `Ok(ColorChoice)` for `auto`, `always` or `never`.
+    /// - `Err(MgrepError::InvalidValue)` for anything else.
+    fn parse(value: &str) -> Result<ColorChoice, MgrepError> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(MgrepError::invalid_value(
+                "--color",
+                value,
+                "auto, always or never",
+            )),
+        }
+    }
+
+    /// Decides whether to color the output, given whether it goes to a terminal.
+    fn should_color(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// The color of the highlighted matches.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HighlightColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    // Bold text in the terminal's own color.
+    Bold,
+}
+
+impl HighlightColor {
+    /// Parses the value of `--highlight-color`.
+    ///
+    /// # Returns
+    /// - `Ok(HighlightColor)` for `red`, `green`, `yellow`, `blue`, `magenta`, `cyan` or `bold`.
+    /// - `Err(MgrepError::UnknownColor)` for anything else.
+    fn parse(value: &str) -> Result<HighlightColor, MgrepError> {
+        match value {
+            "red" => Ok(HighlightColor::Red),
+            "green" => Ok(HighlightColor::Green),
+            "yellow" => Ok(HighlightColor::Yellow),
+            "blue" => Ok(HighlightColor::Blue),
+            "magenta" => Ok(HighlightColor::Magenta),
+            "cyan" => Ok(HighlightColor::Cyan),
+            "bold" => Ok(HighlightColor::Bold),
+            _ => Err(MgrepError::UnknownColor(value.to_string())),
+        }
+    }
+
+    /// The SGR escape sequence that starts the highlighting.
+    fn sgr(self) -> &'static str {
+        match self {
+            HighlightColor::Red => "\x1b[31m",
+            HighlightColor::Green => "\x1b[32m",
+            HighlightColor::Yellow => "\x1b[33m",
+            HighlightColor::Blue => "\x1b[34m",
+            HighlightColor::Magenta => "\x1b[35m",
+            HighlightColor::Cyan => "\x1b[36m",
+            HighlightColor::Bold => "\x1b[1m",
+        }
+    }
+}
+
+/// The colors of the filenames, line numbers, byte offsets and separators in front of the lines, as SGR
+/// parameters (e.g. `35` for magenta, `01;32` for bold green), like grep's `GREP_COLORS`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PrefixColors {
+    // `fn`, also for the filenames printed by `-l`, `-c` and `--heading`.
+    pub filename: String,
+    // `ln`, also for the columns.
+    pub line_number: String,
+    // `bn`
+    pub byte_offset: String,
+    // `se`, the `:` and `-` after each part.
+    pub separator: String,
+}
+
+impl Default for PrefixColors {
+    // grep's default colors.
+    fn default() -> PrefixColors {
+        PrefixColors {
+            filename: String::from("35"),
+            line_number: String::from("32"),
+            byte_offset: String::from("32"),
+            separator: String::from("36"),
+        }
+    }
+}
+
+impl PrefixColors {
+    /// Parses the value of the `MGREP_COLORS` environment variable, e.g. `fn=34:ln=01;33`.
+    ///
+    /// # Returns
+    /// The default colors, overridden by each `fn`, `ln`, `bn` or `se` capability given. An empty value
+    /// leaves that part uncolored. Other capabilities (e.g. grep's `mt`) and values that aren't SGR
+    /// parameters are ignored, as grep does.
+    pub fn parse(value: &str) -> PrefixColors {
+        let mut colors = PrefixColors::default();
+        for capability in value.split(':') {
+            let Some((name, sgr)) = capability.split_once('=') else {
+                continue;
+            };
+            if !sgr.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                continue;
+            }
+            let color = match name {
+                "fn" => &mut colors.filename,
+                "ln" => &mut colors.line_number,
+                "bn" => &mut colors.byte_offset,
+                "se" => &mut colors.separator,
+                _ => continue,
+            };
+            *color = sgr.to_string();
+        }
+        colors
+    }
+}
+
+// How files detected as binary (see `is_binary`) are searched.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryFiles {
+    // Only `Binary file X matches` is printed, if a line matches.
+    Binary,
+    // Binary files are skipped, as if nothing matched.
+    WithoutMatch,
+    // Binary files are searched like text (invalid UTF-8 is replaced).
+    Text,
+}
+
+impl BinaryFiles {
+    /// Parses the value of `--binary-files`.
+    ///
+    /// # Returns
+    /// - `Ok(BinaryFiles)` for `binary`, `without-match` or `text`.
+    /// - `Err(MgrepError::InvalidValue)` for anything else.
+    fn parse(value: &str) -> Result<BinaryFiles, MgrepError> {
+        match value {
+            "binary" => Ok(BinaryFiles::Binary),
+            "without-match" => Ok(BinaryFiles::WithoutMatch),
+            "text" => Ok(BinaryFiles::Text),
+            _ => Err(MgrepError::invalid_value(
+                "--binary-files",
+                value,
+                "binary, without-match or text",
+            )),
+        }
+    }
+}
+
+// The characters that make up the words matched by `-w`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum WordChars {
+    // Any alphanumeric character (accented letters included) or `_`.
+    Unicode,
+    // Only `[A-Za-z0-9_]` (`--ascii-word`).
+    Ascii,
+}
+
+impl WordChars {
+    /// Checks whether `c` is a word character, which can't border a whole-word match.
+    fn contains(self, c: char) -> bool {
+        match self {
+            WordChars::Unicode => c.is_alphanumeric() || c == '_',
+            WordChars::Ascii => c.is_ascii_alphanumeric() || c == '_',
+        }
+    }
+}
+
+// How the patterns are matched (`--engine`), instead of as chosen by `-e`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Engine {
+    // Each pattern containing a regex metacharacter (see `REGEX_METACHARACTERS`) is a regex, the others literals.
+    Auto,
+    // Every pattern is matched literally, like `-F`.
+    Literal,
+    // Every pattern is a regex, like `-e`.
+    Regex,
+}
+
+impl Engine {
+    /// Parses the value of `--engine`.
+    ///
+    /// # Returns
+    /// - `Ok(Engine)` for `auto`, `literal` or `regex`.
+    /// - `Err(MgrepError::InvalidValue)` for anything else.
+    fn parse(value: &str) -> Result<Engine, MgrepError> {
+        match value {
+            "auto" => Ok(Engine::Auto),
+            "literal" => Ok(Engine::Literal),
+            "regex" => Ok(Engine::Regex),
+            _ => Err(MgrepError::invalid_value(
+                "--engine",
+                value,
+                "auto, literal or regex",
+            )),
+        }
+    }
+}
+
+// The characters that make a pattern a regex with `--engine=auto`.
+const REGEX_METACHARACTERS: &str = r".[]()*+?^$|\{}";
+
+// The order the files found by `-r` are searched (and printed) in, instead of the order they were found.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortBy {
+    // Lexicographic order of the paths, component by component (`a/b` before `a.txt`).
+    Path,
+    // Least recently modified first.
+    Modified,
+}
+
+impl SortBy {
+    /// Parses the value of `--sort`.
+    ///
+    /// # Returns
+    /// - `Ok(SortBy)` for `path` or `modified`.
+    /// - `Err(MgrepError::InvalidValue)` for anything else.
+    fn parse(value: &str) -> Result<SortBy, MgrepError> {
+        match value {
+            "path" => Ok(SortBy::Path),
+            "modified" => Ok(SortBy::Modified),
+            _ => Err(MgrepError::invalid_value(
+                "--sort",
+                value,
+                "path or modified",
+            )),
+        }
+    }
+
+    /// Sorts `files` in place. Files whose modification time can't be read come first with `Modified`.
+    fn sort(self, files: &mut [PathBuf]) {
+        match self {
+            SortBy::Path => files.sort(),
+            SortBy::Modified => files.sort_by_cached_key(|file| {
+                (
+                    fs::metadata(file)
+                        .and_then(|metadata| metadata.modified())
+                        .ok(),
+                    file.clone(),
+                )
+            }),
+        }
+    }
 }
 
 // Program command structure
+#[derive(Clone)]
 pub struct Config {
-    pub query: String,
+    // Lines matching any of the patterns are selected.
+    pub patterns: Vec<String>,
     pub ignore_case: bool,
+    pub regex: bool,
+    pub line_number: bool,
+    // The byte offset of each printed line (or of each match with `-o`) in its input.
+    pub byte_offset: bool,
+    pub recursive: bool,
+    pub invert: bool,
+    pub count: bool,
+    // Like `count`, but every occurrence of the query is counted instead of the lines.
+    pub count_matches: bool,
+    // With `count`, a single sum over all the searched files is printed instead of one count per file.
+    pub total: bool,
+    // With `count`, the count is followed by the number of lines searched and their percentage selected.
+    pub ratio: bool,
+    // With `count` over several files, the files without a match are printed too, as `file:0`.
+    pub include_zero: bool,
+    pub word: bool,
+    // With `word`, only ASCII letters, digits and `_` are word characters.
+    pub ascii_word: bool,
+    pub after: usize,
+    pub before: usize,
+    // The line printed between groups of context lines that aren't adjacent, `None` to print nothing.
+    pub group_separator: Option<String>,
+    pub json: bool,
+    pub color: ColorChoice,
+    pub highlight_color: HighlightColor,
+    // From `MGREP_COLORS`, used when the output is colored.
+    pub prefix_colors: PrefixColors,
+    pub threads: usize,
+    pub max_count: Option<usize>,
+    // The search stops once this many lines are selected over all the files, in the order they're printed.
+    pub max_total: Option<usize>,
+    pub files_with_matches: bool,
+    pub files_without_match: bool,
+    pub quiet: bool,
+    pub only_matching: bool,
+    // Glob patterns filtering the file names searched with `-r`.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // Files larger than this many bytes are skipped by `-r`.
+    pub max_filesize: Option<u64>,
+    // How many levels of subdirectories `-r` descends into, `None` for all (0 for the directory's own files).
+    pub max_depth: Option<usize>,
+    // `-r` searches the files matched by `.gitignore` and `.mgrepignore` files too, instead of skipping them.
+    pub no_ignore: bool,
+    // `-r` descends into symlinks to directories too, instead of skipping them.
+    pub follow: bool,
+    // `-r` searches the hidden files and directories (whose name starts with `.`) too, instead of skipping them.
+    pub hidden: bool,
+    // The files and directories that can't be read during a search of many files are skipped silently (`-s`).
+    pub no_messages: bool,
+    // The order of the files searched with `-r`, `None` for the order they're found in.
+    pub sort: Option<SortBy>,
+    pub binary_files: BinaryFiles,
+    // Matches the query literally, even if `regex` is set.
+    pub fixed_strings: bool,
+    // How the patterns are matched, `None` for as `regex` says.
+    pub engine: Option<Engine>,
+    // In regex mode, the group of each match that's highlighted (and printed by `-o`), 0 being the whole match.
+    pub capture: usize,
+    // The query and the lines are compared in Unicode NFC, so precomposed and decomposed accents match.
+    pub normalize: bool,
+    // Filenames are followed by a NUL byte instead of `:` (or a newline with `-l`/`-L`).
+    pub null_data: bool,
+    // The input is made of NUL-terminated records instead of lines, and so is the output.
+    pub null_data_input: bool,
+    pub column: bool,
+    // The printed lines lose their leading and trailing whitespace, which is still searched.
+    pub trim: bool,
+    // The printed lines are cut after this many characters, `None` to print them whole.
+    pub max_columns: Option<usize>,
+    // A matching line identical to the matching line right before it isn't printed again.
+    pub dedupe: bool,
+    // Every line is printed, the selected ones highlighted, instead of only the selected ones.
+    pub passthru: bool,
+    // Each match is printed on its own line as `file:line:column:text`, for Vim's quickfix list.
+    pub vimgrep: bool,
+    // Blank lines are never selected, even if the query matches them.
+    pub skip_blank: bool,
+    // A summary of the files searched and matched, and of the selected lines, is printed on stderr.
+    pub stats: bool,
+    // The encoding of the files (e.g. `latin1`), transcoded to UTF-8 before searching.
+    pub encoding: Option<String>,
+    // The files are memory-mapped instead of read, which saves copying big ones into buffers.
+    pub mmap: bool,
+    // The files ending in `.gz` are decompressed while they're searched.
+    pub search_zip: bool,
+    // The command run on each file (e.g. `zcat`), whose output is searched instead of the file's contents.
+    pub pre: Option<String>,
+    // The search is stopped with an error after this long, and so is each `pre` command.
+    pub timeout: Option<Duration>,
+    // With `-r`, the number of files searched so far is reported on stderr, if it's a terminal.
+    pub progress: bool,
+    // The files under the directory are listed instead of searched, and there's no query.
+    pub files: bool,
+    // The output is flushed before waiting for more input, so matches show up as soon as they're found.
+    pub line_buffered: bool,
+    // With `-r`, the matches of each file are printed under its name instead of prefixed by it.
+    pub heading: bool,
+    // With many files, the matches of each file are separated from the previous file's by a blank line.
+    pub separate_files: bool,
+    // Whether lines are prefixed by their filename (`-H`, `--no-filename`), `None` for only with many files.
+    pub with_filename: Option<bool>,
+    // The query must be at the start (`--line-start`) or end (`--line-end`) of the line; both mean the whole line.
+    pub anchor_start: bool,
+    pub anchor_end: bool,
+    // The query must match the whole line (`-x`), like both anchors together.
+    pub line_regexp: bool,
+    // The whole input is searched at once instead of line by line, so that matches can span lines (`-U`).
+    pub multiline: bool,
+    // With `multiline`, a regex `.` also matches the line breaks.
+    pub multiline_dotall: bool,
+    // The text each match is replaced with in the printed lines.
+    pub replace: Option<String>,
+    // The file the results are written to, instead of stdout.
+    pub output: Option<String>,
+    // Set by `run` once its `timeout` expires, so the search it leaves behind stops reading and writing.
+    pub(crate) cancelled: Arc<AtomicBool>,
     pub input: InputType,
 }
 
+// The byte order mark that may start a UTF-8 file, skipped before searching.
+const UTF8_BOM: &str = "\u{feff}";
+
+// How stdin is named where a filename is expected.
+const STDIN_NAME: &str = "(standard input)";
+
+// Flags that consume the following argument as their value (unless given as `--flag=value`).
+const VALUE_FLAGS: [&str; 35] = [
+    "-A",
+    "--after-context",
+    "-B",
+    "--before-context",
+    "-C",
+    "--context",
+    "--group-separator",
+    "--color",
+    "--highlight-color",
+    "--include",
+    "--exclude",
+    "--max-filesize",
+    "--max-depth",
+    "--max-columns",
+    "--capture",
+    "--sort",
+    "--binary-files",
+    "--encoding",
+    "--engine",
+    "--pre",
+    "--timeout",
+    "-j",
+    "--threads",
+    "-m",
+    "--max-count",
+    "--max-total",
+    "-f",
+    "--file",
+    "--patterns-from",
+    "-p",
+    "--pattern",
+    "-O",
+    "--output",
+    "-R",
+    "--replace",
+];
+
+// Short flags that take no value, which can be combined with each other (e.g. `-in` for `-i -n`).
+const SHORT_FLAGS: [&str; 22] = [
+    "-i", "-S", "-e", "-F", "-n", "-b", "-r", "-v", "-c", "-w", "-x", "-z", "-Z", "-a", "-l", "-L",
+    "-o", "-q", "-s", "-h", "-H", "-U",
+];
+
+// The short and long forms of a flag, the same for long-only flags.
+type Flag = (&'static str, &'static str);
+
+// The flags that can't be given together, because one would silently override the other or their output
+// would be meaningless (e.g. `-o -v`: inverted lines contain no match to print).
+const CONFLICTS: [(Flag, Flag); 35] = [
+    (
+        ("-l", "--files-with-matches"),
+        ("-L", "--files-without-match"),
+    ),
+    (("-l", "--files-with-matches"), ("-c", "--count")),
+    (
+        ("-l", "--files-with-matches"),
+        ("--count-matches", "--count-matches"),
+    ),
+    (("-l", "--files-with-matches"), ("-o", "--only-matching")),
+    (("-l", "--files-with-matches"), ("--json", "--json")),
+    (("-L", "--files-without-match"), ("-c", "--count")),
+    (
+        ("-L", "--files-without-match"),
+        ("--count-matches", "--count-matches"),
+    ),
+    (("-L", "--files-without-match"), ("-o", "--only-matching")),
+    (("-L", "--files-without-match"), ("--json", "--json")),
+    (("-c", "--count"), ("--count-matches", "--count-matches")),
+    (("-c", "--count"), ("--json", "--json")),
+    (
+        ("--count-matches", "--count-matches"),
+        ("-o", "--only-matching"),
+    ),
+    (("--count-matches", "--count-matches"), ("--json", "--json")),
+    (
+        ("--count-matches", "--count-matches"),
+        ("-v", "--invert-match"),
+    ),
+    (("-o", "--only-matching"), ("-v", "--invert-match")),
+    (("-l", "--files-with-matches"), ("--passthru", "--passthru")),
+    (
+        ("-L", "--files-without-match"),
+        ("--passthru", "--passthru"),
+    ),
+    (("-c", "--count"), ("--passthru", "--passthru")),
+    (
+        ("--count-matches", "--count-matches"),
+        ("--passthru", "--passthru"),
+    ),
+    (("-o", "--only-matching"), ("--passthru", "--passthru")),
+    (("--json", "--json"), ("--passthru", "--passthru")),
+    (("-l", "--files-with-matches"), ("--vimgrep", "--vimgrep")),
+    (("-L", "--files-without-match"), ("--vimgrep", "--vimgrep")),
+    (("-c", "--count"), ("--vimgrep", "--vimgrep")),
+    (
+        ("--count-matches", "--count-matches"),
+        ("--vimgrep", "--vimgrep"),
+    ),
+    (("-o", "--only-matching"), ("--vimgrep", "--vimgrep")),
+    (("--json", "--json"), ("--vimgrep", "--vimgrep")),
+    (("-v", "--invert-match"), ("--vimgrep", "--vimgrep")),
+    (("--passthru", "--passthru"), ("--vimgrep", "--vimgrep")),
+    (("-U", "--multiline"), ("--json", "--json")),
+    (("-U", "--multiline"), ("-v", "--invert-match")),
+    (("-U", "--multiline"), ("--passthru", "--passthru")),
+    (("-U", "--multiline"), ("--vimgrep", "--vimgrep")),
+    (("-U", "--multiline"), ("-R", "--replace")),
+    (("-U", "--multiline"), ("--dedupe", "--dedupe")),
+];
+
 // That's the core method of the program.
 impl Config {
+    /// Builds the program configuration from the arguments alone, without a program name in front, e.g.
+    /// `Config::build_from_args(&["fox", "-n", "poem.txt"])`.
+    ///
+    /// # Returns
+    /// The same as `Config::build`, which it delegates to.
+    pub fn build_from_args(args: &[&str]) -> Result<Config, MgrepError> {
+        Config::build(
+            std::iter::once("mgrep")
+                .chain(args.iter().copied())
+                .map(str::to_string),
+        )
+    }
+
     /// Builds the program configuration based on the arguments passed by the command.
     ///
     /// # Arguments
@@ -36,294 +600,5054 @@ impl Config {
     ///
     /// # Returns
     /// - `Ok(Config)`: Successfully creates a `Config` object configured with the provided arguments.
-    /// - `Err(Box<dyn Error>)`: Returns an error if any of the necessary components (query, ignore_case, input)
+    /// - `Err(MgrepError)`: Returns an error if any of the necessary components (query, ignore_case, input)
     ///   cannot be properly retrieved or parsed.
-    pub fn build<I>(mut args: I) -> Result<Config, Box<dyn Error>>
+    pub fn build<I>(args: I) -> Result<Config, MgrepError>
     where
         I: Iterator<Item = String>,
     {
-        // Skip the first arg (program name).
-        args.next();
-
-        let required_args: Vec<String> = args.collect();
-
-        let query = Config::get_query(&mut required_args.iter().map(|s| s.to_string()))?;
-        let ignore_case = Config::get_ignore_case(&required_args);
-        let input = Config::get_input(&mut required_args.iter().map(|s| s.to_string()))?;
-
-        Ok(Config {
-            query,
-            ignore_case,
-            input,
-        })
+        Config::build_with_env(args, |name| env::var_os(name))
     }
 
-    /// Get a query string in the 'arg[1]' to find't.
-    /// But not before checking whether the arg contains a help.
-    ///
-    /// # Arguments
-    /// - `args`: An Iterator of strings representing command line arguments.
-    ///
-    /// # Returns
-    /// - `Ok(arg)` if get a query string.
-    /// - `exit(1)` if get the help expression.
-    /// - `Err()` if didn't get the previous values.
-    fn get_query<I>(args: &mut I) -> Result<String, Box<dyn Error>>
+    // Same as `build`, with the environment variables (`IGNORE_CASE`, `NO_COLOR`, `MGREP_COLORS`) read through
+    // `env_var` instead of from the process, so that tests don't have to change its environment.
+    fn build_with_env<I>(
+        mut args: I,
+        env_var: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<Config, MgrepError>
     where
         I: Iterator<Item = String>,
     {
-        if let Some(arg) = args.next() {
-            if !arg.contains("-h") || !arg.contains("--help") {
-                Ok(arg)
-            } else {
-                println!("{}", Config::help_message());
-                std::process::exit(1);
+        // Skip the first arg (program name).
+        args.next();
+
+        let required_args = Config::split_clusters(args.collect())?;
+
+        // With `-f` or `-p`, the patterns come from a file or the flags and there's no positional query,
+        // nor with `--files`.
+        let files = required_args.iter().any(|arg| arg == "--files");
+        let progress = required_args.iter().any(|arg| arg == "--progress");
+        // `--patterns-from` is a synonym of `-f`, and wins over it.
+        let pattern_file = Config::get_value(&required_args, "--patterns-from", "--patterns-from")
+            .or_else(|| Config::get_value(&required_args, "-f", "--file"));
+        let pattern_args = Config::get_pattern_args(&required_args)?;
+        let (mut patterns, query_index) = match pattern_file {
+            Some(path) => (Config::get_pattern_file(path)?, None),
+            None if files || !pattern_args.is_empty() => (Vec::new(), None),
+            None => {
+                let (index, query) = Config::get_query(&required_args)?;
+                (vec![query.to_string()], Some(index))
             }
+        };
+        patterns.extend(pattern_args);
+        let ignore_case = Config::get_ignore_case(
+            &required_args,
+            &patterns.concat(),
+            env_var("IGNORE_CASE")
+                .and_then(|value| value.into_string().ok())
+                .as_deref(),
+        );
+        let regex = Config::has_flag(&required_args, "-e", "--regex");
+        let line_number = Config::has_flag(&required_args, "-n", "--line-number");
+        let byte_offset = Config::has_flag(&required_args, "-b", "--byte-offset");
+        let recursive = Config::has_flag(&required_args, "-r", "--recursive");
+        let invert = Config::has_flag(&required_args, "-v", "--invert-match");
+        let count = Config::has_flag(&required_args, "-c", "--count");
+        let count_matches = required_args.iter().any(|arg| arg == "--count-matches");
+        let total = required_args.iter().any(|arg| arg == "--total");
+        let ratio = required_args.iter().any(|arg| arg == "--ratio");
+        let include_zero = required_args.iter().any(|arg| arg == "--include-zero");
+        let word = Config::has_flag(&required_args, "-w", "--word-regexp");
+        let ascii_word = required_args.iter().any(|arg| arg == "--ascii-word");
+        // `-A`/`-B` take precedence over the `-C` value for their own side.
+        let context = Config::get_number(&required_args, "-C", "--context")?.unwrap_or(0);
+        let after = Config::get_number(&required_args, "-A", "--after-context")?.unwrap_or(context);
+        let before =
+            Config::get_number(&required_args, "-B", "--before-context")?.unwrap_or(context);
+        let json = required_args.iter().any(|arg| arg == "--json");
+        let color = Config::get_color(&required_args, env_var("NO_COLOR").is_some())?;
+        let highlight_color =
+            match Config::get_value(&required_args, "--highlight-color", "--highlight-color") {
+                Some(value) => HighlightColor::parse(value)?,
+                None => HighlightColor::Red,
+            };
+        let prefix_colors = env_var("MGREP_COLORS")
+            .and_then(|value| value.into_string().ok())
+            .map_or_else(PrefixColors::default, |value| PrefixColors::parse(&value));
+        let threads = Config::get_threads(&required_args)?;
+        let max_count = Config::get_number(&required_args, "-m", "--max-count")?;
+        let max_total = Config::get_number(&required_args, "--max-total", "--max-total")?;
+        let files_with_matches = Config::has_flag(&required_args, "-l", "--files-with-matches");
+        let files_without_match = Config::has_flag(&required_args, "-L", "--files-without-match");
+        Config::check_conflicts(&required_args)?;
+        let fixed_strings = Config::has_flag(&required_args, "-F", "--fixed-strings");
+        let engine = Config::get_value(&required_args, "--engine", "--engine")
+            .map(Engine::parse)
+            .transpose()?;
+        let normalize = required_args.iter().any(|arg| arg == "--normalize");
+        let capture = Config::get_number(&required_args, "--capture", "--capture")?.unwrap_or(0);
+        let quiet = Config::has_flag(&required_args, "-q", "--quiet");
+        let null_data = Config::has_flag(&required_args, "-Z", "--null");
+        let null_data_input = Config::has_flag(&required_args, "-z", "--null-data");
+        let column = required_args.iter().any(|arg| arg == "--column");
+        let trim = required_args.iter().any(|arg| arg == "--trim");
+        let dedupe = required_args.iter().any(|arg| arg == "--dedupe");
+        let passthru = required_args.iter().any(|arg| arg == "--passthru");
+        let vimgrep = required_args.iter().any(|arg| arg == "--vimgrep");
+        let skip_blank = required_args.iter().any(|arg| arg == "--skip-blank");
+        let stats = required_args.iter().any(|arg| arg == "--stats");
+        let line_buffered = required_args.iter().any(|arg| arg == "--line-buffered");
+        let mmap = required_args.iter().any(|arg| arg == "--mmap");
+        let search_zip = required_args.iter().any(|arg| arg == "--search-zip");
+        let pre = Config::get_value(&required_args, "--pre", "--pre").map(str::to_string);
+        let timeout = Config::get_value(&required_args, "--timeout", "--timeout")
+            .map(Config::parse_duration)
+            .transpose()?;
+        let heading = required_args.iter().any(|arg| arg == "--heading");
+        let separate_files = required_args.iter().any(|arg| arg == "--separate-files");
+        let with_filename = Config::get_with_filename(&required_args);
+        let anchor_start = required_args.iter().any(|arg| arg == "--line-start");
+        let anchor_end = required_args.iter().any(|arg| arg == "--line-end");
+        let line_regexp = Config::has_flag(&required_args, "-x", "--line-regexp");
+        let multiline = Config::has_flag(&required_args, "-U", "--multiline");
+        let multiline_dotall = required_args.iter().any(|arg| arg == "--multiline-dotall");
+        if multiline_dotall && !multiline {
+            return Err(MgrepError::Usage(
+                "--multiline-dotall only applies to multiline searches, add -U".to_string(),
+            ));
+        }
+        let replace = Config::get_value(&required_args, "-R", "--replace").map(str::to_string);
+        let group_separator = if required_args
+            .iter()
+            .any(|arg| arg == "--no-group-separator")
+        {
+            None
+        } else {
+            Some(
+                Config::get_value(&required_args, "--group-separator", "--group-separator")
+                    .unwrap_or("--")
+                    .to_string(),
+            )
+        };
+        let output = Config::get_value(&required_args, "-O", "--output").map(str::to_string);
+        let only_matching = Config::has_flag(&required_args, "-o", "--only-matching");
+        let include = Config::get_values(&required_args, "--include", "--include");
+        let exclude = Config::get_values(&required_args, "--exclude", "--exclude");
+        let max_depth = Config::get_number(&required_args, "--max-depth", "--max-depth")?;
+        let max_columns = Config::get_number(&required_args, "--max-columns", "--max-columns")?;
+        let no_ignore = required_args.iter().any(|arg| arg == "--no-ignore");
+        let follow = required_args.iter().any(|arg| arg == "--follow");
+        let hidden = required_args.iter().any(|arg| arg == "--hidden");
+        let no_messages = Config::get_no_messages(&required_args);
+        let max_filesize = Config::get_value(&required_args, "--max-filesize", "--max-filesize")
+            .map(Config::parse_size)
+            .transpose()?;
+        let sort = Config::get_value(&required_args, "--sort", "--sort")
+            .map(SortBy::parse)
+            .transpose()?;
+        // `-a` is a shorthand for `--binary-files=text`, and wins over it.
+        let binary_files = if Config::has_flag(&required_args, "-a", "--text") {
+            BinaryFiles::Text
         } else {
-            Err("Didn't get a query string".into())
+            match Config::get_value(&required_args, "--binary-files", "--binary-files") {
+                Some(value) => BinaryFiles::parse(value)?,
+                None => BinaryFiles::Binary,
+            }
+        };
+        let encoding = Config::get_value(&required_args, "--encoding", "--encoding")
+            .map(|label| encoding_for(label).map(|_| label.to_string()))
+            .transpose()?;
+        // Stdin can't be listed, so it isn't waited for.
+        if files && !Config::positionals(&required_args).any(|(_, arg)| Path::new(arg).is_dir()) {
+            return Err(MgrepError::Usage(
+                "--files needs a directory to list (e.g. mgrep --files src)".to_string(),
+            ));
+        }
+        let input = Config::get_input(&required_args, query_index, io::stdin().is_terminal())?;
+        // Stdin was read to the end for the patterns, so it can't hold the text to search too.
+        if pattern_file == Some("-")
+            && matches!(input, InputType::LiteralInput(_) | InputType::Stdin)
+        {
+            return Err(MgrepError::Usage(
+                "the patterns are read from stdin, so the text to search must be in a FILE \
+                (e.g. generate-patterns | mgrep --patterns-from=- file.txt)"
+                    .to_string(),
+            ));
         }
+
+        let config = Config::new(String::new(), input)
+            .with_patterns(patterns)
+            .with_ignore_case(ignore_case)
+            .with_regex(regex)
+            .with_line_number(line_number)
+            .with_byte_offset(byte_offset)
+            .with_recursive(recursive)
+            .with_invert(invert)
+            .with_count(count)
+            .with_count_matches(count_matches)
+            .with_total(total)
+            .with_ratio(ratio)
+            .with_include_zero(include_zero)
+            .with_word(word)
+            .with_ascii_word(ascii_word)
+            .with_after_context(after)
+            .with_before_context(before)
+            .with_group_separator(group_separator)
+            .with_json(json)
+            .with_color(color)
+            .with_highlight_color(highlight_color)
+            .with_prefix_colors(prefix_colors)
+            .with_threads(threads)
+            .with_max_count(max_count)
+            .with_max_total(max_total)
+            .with_files_with_matches(files_with_matches)
+            .with_files_without_match(files_without_match)
+            .with_quiet(quiet)
+            .with_only_matching(only_matching)
+            .with_include(include.into_iter().map(str::to_string).collect())
+            .with_exclude(exclude.into_iter().map(str::to_string).collect())
+            .with_max_filesize(max_filesize)
+            .with_max_depth(max_depth)
+            .with_no_ignore(no_ignore)
+            .with_follow(follow)
+            .with_hidden(hidden)
+            .with_no_messages(no_messages)
+            .with_sort(sort)
+            .with_binary_files(binary_files)
+            .with_fixed_strings(fixed_strings)
+            .with_engine(engine)
+            .with_capture(capture)
+            .with_normalize(normalize)
+            .with_null_data(null_data)
+            .with_null_data_input(null_data_input)
+            .with_column(column)
+            .with_trim(trim)
+            .with_max_columns(max_columns)
+            .with_dedupe(dedupe)
+            .with_passthru(passthru)
+            .with_vimgrep(vimgrep)
+            .with_skip_blank(skip_blank)
+            .with_stats(stats)
+            .with_encoding(encoding)
+            .with_mmap(mmap)
+            .with_search_zip(search_zip)
+            .with_pre(pre)
+            .with_timeout(timeout)
+            .with_files(files)
+            .with_progress(progress)
+            .with_line_buffered(line_buffered)
+            .with_heading(heading)
+            .with_separate_files(separate_files)
+            .with_filename(with_filename)
+            .with_anchor_start(anchor_start)
+            .with_anchor_end(anchor_end)
+            .with_line_regexp(line_regexp)
+            .with_multiline(multiline)
+            .with_multiline_dotall(multiline_dotall)
+            .with_replace(replace)
+            .with_output(output);
+        config.check_capture()?;
+
+        Ok(config)
     }
 
-    /// Determines the case sensitivity for the command based on the provided arguments and environment.
-    ///
-    /// The function first checks command line arguments for `-i` (`--ignore-case`) or `-ni` (`--no-ignore-case`).
-    /// If neither is specified, it checks the `IGNORE_CASE` environment variable.
+    /// Creates a configuration programmatically, without going through command-line arguments.
+    /// Every option starts disabled (case-sensitive literal search, no context, `--color=auto`, one
+    /// thread per logical CPU) and can be changed with the chainable `with_*` setters.
     ///
     /// # Arguments
-    /// - `args`: A slice of strings representing command line arguments.
+    /// - `query`: The text (or regular expression, see `with_regex`) to search for.
+    /// - `input`: Where to search: a file (or directory) path, or the text itself.
     ///
-    /// # Returns
-    /// - `true` if case sensitivity should be ignored (case-insensitive mode).
-    /// - `false` if case sensitivity should be respected (case-sensitive mode).
+    /// # Examples
+    /// ```
+    /// use mgrep::{Config, InputType};
     ///
-    /// # Note
-    /// - The `-ni` or `--no-ignore-case` argument takes precedence over the `IGNORE_CASE` environment variable.
-    fn get_ignore_case(args: &[String]) -> bool {
-        let ignore_case_flag = args.iter().any(|arg| arg == "-i" || arg == "--ignore-case");
-        let no_ignore_case_flag = args
-            .iter()
-            .any(|arg| arg == "-ni" || arg == "--no-ignore-case");
-
-        // Check command line flags first for explicit setting
-        if no_ignore_case_flag {
-            false
-        } else if ignore_case_flag {
-            true
-        } else {
-            // If no flags are specified, default to the environment variable
-            env::var("IGNORE_CASE").is_ok()
-        }
-    }
-
-    /// Distinguishes between file path and command and returns the InputType.
+    /// let config = Config::new(
+    ///     "rust".to_string(),
+    ///     InputType::LiteralInput("Rust:\nsafe, fast, productive.\nTrust me.".to_string()),
+    /// )
+    /// .with_ignore_case(true)
+    /// .with_line_number(true);
     ///
-    /// # Arguments
-    /// - `args` An Iterator of strings representing command line arguments.
+    /// // Prints "1:Rust:" and "3:Trust me." to stdout.
+    /// mgrep::run(config).unwrap();
+    /// ```
     ///
-    /// - `Ok(InputType::FilePath(String))`: Returns a `FilePath` variant of `InputType` if one of the arguments
-    ///   contains a '/' or '\\' indicating a path.
-    /// - `Ok(InputType::LiteralInput(String))`: Returns a `LiteralInput` variant of `InputType` if no path is
-    ///   detected. It reads the entire input from stdin, assuming it to be a direct text input.
-    /// - `Err(Box<dyn Error>)`: Returns an error if there are issues reading from stdin.
-    fn get_input<'a, I>(args: &mut I) -> Result<InputType, Box<dyn Error>>
-    where
-        I: Iterator<Item = String>,
-    {
-        // Checks if it is a file path.
-        if let Some(arg) = args.find(|arg| arg.contains('/') || arg.contains('\\')) {
-            Ok(InputType::FilePath(arg))
-        // Understands that it is a command.
-        } else {
-            let mut input_line = String::new();
-            io::stdin().read_to_string(&mut input_line)?;
-
-            Ok(InputType::LiteralInput(
-                input_line.trim_matches('"').to_string(),
-            ))
+    /// The matching lines can also be collected instead of printed:
+    /// ```
+    /// let contents = "Rust:\nsafe, fast, productive.\nTrust me.";
+    /// let matcher = mgrep::Matcher::new("rust", true, false, false).unwrap();
+    /// let results = mgrep::search(&matcher, false, contents);
+    ///
+    /// assert_eq!(vec![(1, "Rust:"), (3, "Trust me.")], results);
+    /// ```
+    pub fn new(query: String, input: InputType) -> Config {
+        Config {
+            patterns: vec![query],
+            ignore_case: false,
+            regex: false,
+            line_number: false,
+            byte_offset: false,
+            recursive: false,
+            invert: false,
+            count: false,
+            count_matches: false,
+            total: false,
+            ratio: false,
+            include_zero: false,
+            word: false,
+            ascii_word: false,
+            after: 0,
+            before: 0,
+            group_separator: Some("--".to_string()),
+            json: false,
+            color: ColorChoice::Auto,
+            highlight_color: HighlightColor::Red,
+            prefix_colors: PrefixColors::default(),
+            threads: Config::default_threads(),
+            max_count: None,
+            max_total: None,
+            files_with_matches: false,
+            files_without_match: false,
+            quiet: false,
+            only_matching: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_filesize: None,
+            max_depth: None,
+            no_ignore: false,
+            follow: false,
+            hidden: false,
+            no_messages: false,
+            sort: None,
+            binary_files: BinaryFiles::Binary,
+            fixed_strings: false,
+            engine: None,
+            capture: 0,
+            normalize: false,
+            null_data: false,
+            null_data_input: false,
+            column: false,
+            trim: false,
+            max_columns: None,
+            dedupe: false,
+            passthru: false,
+            vimgrep: false,
+            skip_blank: false,
+            stats: false,
+            encoding: None,
+            mmap: false,
+            search_zip: false,
+            pre: None,
+            timeout: None,
+            files: false,
+            progress: false,
+            line_buffered: false,
+            heading: false,
+            separate_files: false,
+            with_filename: None,
+            anchor_start: false,
+            anchor_end: false,
+            line_regexp: false,
+            multiline: false,
+            multiline_dotall: false,
+            replace: None,
+            output: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            input,
         }
     }
 
-    /// This function is used when get_query() realize that it
-    /// contains an arg by calling the help.
-    fn help_message() -> String {
-        "Usage:
-        File Path:
-        cargo run -- [\"QUERY\"] [PATH] [EXPRESSION]
+    /// Replaces the query with several patterns: lines matching any of them are selected (`-f`).
+    pub fn with_patterns(mut self, patterns: Vec<String>) -> Config {
+        self.patterns = patterns;
+        self
+    }
 
-        String Literal One Line:
-        echo \"The literal string go here\" | cargo run -- [\"QUERY\"] [EXPRESSION]
+    /// Sets whether the search ignores case sensitivity (`-i`).
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Config {
+        self.ignore_case = ignore_case;
+        self
+    }
 
-        String Literal Multiple Lines:
-        cat << EOF | cargo run -- [\"QUERY\"] [EXPRESSION]
-        The literal string
-        go
-        here
-        EOF
+    /// Sets whether the query is a regular expression (`-e`).
+    pub fn with_regex(mut self, regex: bool) -> Config {
+        self.regex = regex;
+        self
+    }
 
-        Help:
-        cargo run -- [EXPRESSION]
+    /// Sets whether matching lines are prefixed by their line number (`-n`).
+    pub fn with_line_number(mut self, line_number: bool) -> Config {
+        self.line_number = line_number;
+        self
+    }
 
-        Expressions:
-        -i, --ignore-case        ignore case sensitive in search
-        -ni, --no-ignore-case    don't ignore case sensitive in search
-        -h, --help               display this help and exit
-        
-        Environment Variable Usage:
-        IGNORE_CASE=1            ignore case sensitive in search"
-            .to_string()
+    /// Sets whether printed lines are prefixed by the byte offset where they start in the input, or
+    /// matches by their own offset with `-o` (`-b`).
+    pub fn with_byte_offset(mut self, byte_offset: bool) -> Config {
+        self.byte_offset = byte_offset;
+        self
     }
-}
 
-/// That's the core function of the program.
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    // See the description in `Config::get_input()`
-    let contents = match config.input {
-        InputType::FilePath(path) => fs::read_to_string(path)?,
-        InputType::LiteralInput(text) => text,
-    };
+    /// Sets whether a directory input is searched recursively (`-r`).
+    pub fn with_recursive(mut self, recursive: bool) -> Config {
+        self.recursive = recursive;
+        self
+    }
 
-    // Searches for the ´query´
-    let results = search(&config.query, config.ignore_case, &contents);
+    /// Sets whether the lines that do NOT match are selected instead (`-v`).
+    pub fn with_invert(mut self, invert: bool) -> Config {
+        self.invert = invert;
+        self
+    }
 
-    // Print the query
-    results
-        .iter()
-        .for_each(|line| print_highlighted(&config.query, config.ignore_case, line));
+    /// Sets whether only the number of selected lines is printed (`-c`).
+    pub fn with_count(mut self, count: bool) -> Config {
+        self.count = count;
+        self
+    }
 
-    Ok(())
-}
+    /// Sets whether only the number of occurrences of the query is printed (`--count-matches`).
+    pub fn with_count_matches(mut self, count_matches: bool) -> Config {
+        self.count_matches = count_matches;
+        self
+    }
 
-// Print the matched query in highlighted style.
-fn print_highlighted(query: &str, ignore_case: bool, line: &str) {
-    let mut start = 0;
-    let query_len = query.len();
+    /// Sets whether `-c` prints a single count summed over all the searched files (`--total`).
+    pub fn with_total(mut self, total: bool) -> Config {
+        self.total = total;
+        self
+    }
 
-    // Adjustment for case sensitive.
-    let target_line = if ignore_case {
-        line.to_lowercase()
-    } else {
-        line.to_string()
-    };
+    /// Sets whether each `-c` count is followed by the number of lines searched and the percentage of them
+    /// selected, e.g. `42/1000 (4.2%)` (`--ratio`).
+    pub fn with_ratio(mut self, ratio: bool) -> Config {
+        self.ratio = ratio;
+        self
+    }
 
-    // Adjustment for case sensitive.
-    let target_query = if ignore_case {
-        query.to_lowercase()
-    } else {
-        query.to_string()
-    };
+    /// Sets whether the files without a match still get a `file:0` count when counting over several files
+    /// (`--include-zero`).
+    pub fn with_include_zero(mut self, include_zero: bool) -> Config {
+        self.include_zero = include_zero;
+        self
+    }
 
-    while let Some(position) = target_line[start..].find(&target_query) {
-        // Prints before the word.
-        print!("{}", &line[start..start + position]);
+    /// Sets whether the query must match a whole word (`-w`).
+    pub fn with_word(mut self, word: bool) -> Config {
+        self.word = word;
+        self
+    }
 
-        // Prints the highlighted word.
-        print!(
-            "\x1b[31m{}\x1b[0m",
-            &line[start + position..start + position + query_len]
-        );
+    /// Sets whether only ASCII letters, digits and `_` are word characters with `-w` (`--ascii-word`).
+    pub fn with_ascii_word(mut self, ascii_word: bool) -> Config {
+        self.ascii_word = ascii_word;
+        self
+    }
 
-        // Updates the starting position to after the word.
-        start += position + query_len;
+    /// Sets the number of context lines printed after each match (`-A`).
+    pub fn with_after_context(mut self, after: usize) -> Config {
+        self.after = after;
+        self
     }
 
-    // Prints the remaining line.
-    println!("{}", &line[start..]);
-}
+    /// Sets the number of context lines printed before each match (`-B`).
+    pub fn with_before_context(mut self, before: usize) -> Config {
+        self.before = before;
+        self
+    }
 
-/// Searches the given content for lines that contain the specified query.
-///
-/// # Parameters
-/// - `query`: The text string to search for within each line of `contents`.
-/// - `ignore_case`: A boolean indicating whether the search should be case insensitive.
-/// - `contents`: The text within which to search for `query`.
-///
-/// # Returns
-/// A vector of strings, each a line from `contents` that matches the `query` based on the specified case sensitivity.
-fn search<'a>(query: &str, ignore_case: bool, contents: &'a str) -> Vec<&'a str> {
-    // Convert the query to lowercase if the search is case insensitive, done once for efficiency.
-    let query = if ignore_case {
-        query.to_lowercase()
-    } else {
-        query.to_string()
-    };
+    /// Sets the line printed between non-adjacent groups of context lines (`--group-separator`), or
+    /// `None` to print nothing between them (`--no-group-separator`).
+    pub fn with_group_separator(mut self, group_separator: Option<String>) -> Config {
+        self.group_separator = group_separator;
+        self
+    }
 
-    // Define a line filter function: uses a dynamic dispatch via Box<dyn Fn(&str) -> bool>.
-    // This allows switching the filtering function based on `ignore_case`.
+    /// Sets whether matching lines are printed as JSON objects (`--json`).
+    pub fn with_json(mut self, json: bool) -> Config {
+        self.json = json;
+        self
+    }
 
-    let line_filter = if ignore_case {
-        // For case-insensitive search, compare each line in lowercase to the lowercase query.
-        Box::new(|line: &str| line.to_lowercase().contains(&query.to_lowercase()))
-    } else {
-        // For case-sensitive search, directly check if the line contains the query.
-        Box::new(|line: &str| line.contains(&query)) as Box<dyn Fn(&str) -> bool>
-    };
+    /// Sets when matches are highlighted (`--color`).
+    pub fn with_color(mut self, color: ColorChoice) -> Config {
+        self.color = color;
+        self
+    }
 
-    // Process each line of the contents, filtering based on the presence of the query
-    // as determined by the line_filter function. Collect matching lines into a vector.
-    contents.lines().filter(|line| line_filter(line)).collect()
-}
+    /// Sets the color of the highlighted matches (`--highlight-color`).
+    pub fn with_highlight_color(mut self, highlight_color: HighlightColor) -> Config {
+        self.highlight_color = highlight_color;
+        self
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Sets the colors of the line prefixes, when the output is colored.
+    pub fn with_prefix_colors(mut self, prefix_colors: PrefixColors) -> Config {
+        self.prefix_colors = prefix_colors;
+        self
+    }
 
-    // Tests the search for words in a case-sensitive manner.
-    #[test]
-    fn case_sensitive() {
-        let ignore_case = false;
-        let query = "duct";
-        let contents = "\
-Rust:
-safe, fast, productive.
-Pick three.
-Duct tape.";
+    /// Sets how many files are searched in parallel (`-j`).
+    pub fn with_threads(mut self, threads: usize) -> Config {
+        self.threads = threads;
+        self
+    }
 
-        assert_eq!(
-            vec!["safe, fast, productive."],
-            search(query, ignore_case, contents)
-        );
+    /// Sets the number of matching lines after which each file stops being read (`-m`).
+    pub fn with_max_count(mut self, max_count: Option<usize>) -> Config {
+        self.max_count = max_count;
+        self
     }
 
-    // Tests the search for words in a case-insensitive manner.
-    #[test]
-    fn case_insensitive() {
-        let ignore_case = true;
-        let query = "rUsT";
-        let contents = "\
-Rust:
-safe, fast, productive.
-Pick three.
-Trust me.";
+    /// Sets the number of matching lines, over all the searched files, after which the search stops
+    /// (`--max-total`). They're the first ones in the order the files are printed, even when searched in
+    /// parallel.
+    pub fn with_max_total(mut self, max_total: Option<usize>) -> Config {
+        self.max_total = max_total;
+        self
+    }
 
-        assert_eq!(
-            vec!["Rust:", "Trust me."],
-            search(query, ignore_case, contents)
-        );
+    /// Sets whether only the names of the files with a match are printed (`-l`).
+    pub fn with_files_with_matches(mut self, files_with_matches: bool) -> Config {
+        self.files_with_matches = files_with_matches;
+        self
     }
 
-    // Tests if the option to ignore case is applied when defined twice,
-    // once by the environment and once by the argument.
-    #[test]
-    #[ignore = "failure due to lack of interest"]
-    fn double_opposite_case() {
-        // Sets the environment variable to ignore case.
-        std::env::set_var("IGNORE_CASE", "1");
+    /// Sets whether only the names of the files without any match are printed (`-L`).
+    pub fn with_files_without_match(mut self, files_without_match: bool) -> Config {
+        self.files_without_match = files_without_match;
+        self
+    }
 
-        // Sets the command arguments, including the option to ignore case.
-        let args = vec![
-            "".to_string(),
-            "to".to_string(),
-            "-i".to_string(),
-            "poem.txt".to_string(),
-        ];
+    /// Sets whether nothing is printed, the match only being reported by the exit code (`-q`).
+    pub fn with_quiet(mut self, quiet: bool) -> Config {
+        self.quiet = quiet;
+        self
+    }
 
-        // Create the iterator based on arguments vector.
-        let args_iter = args.into_iter();
+    /// Sets whether only the matched parts of the lines are printed, one per line (`-o`).
+    pub fn with_only_matching(mut self, only_matching: bool) -> Config {
+        self.only_matching = only_matching;
+        self
+    }
 
-        // Creates the configuration based on the arguments.
-        let config = Config::build(args_iter).unwrap();
+    /// Sets the glob patterns of the file names to search with `-r` (`--include`).
+    pub fn with_include(mut self, include: Vec<String>) -> Config {
+        self.include = include;
+        self
+    }
+
+    /// Sets the glob patterns of the file names to skip with `-r`, even if included (`--exclude`).
+    pub fn with_exclude(mut self, exclude: Vec<String>) -> Config {
+        self.exclude = exclude;
+        self
+    }
+
+    /// Sets the size in bytes above which files are skipped with `-r` (`--max-filesize`).
+    pub fn with_max_filesize(mut self, max_filesize: Option<u64>) -> Config {
+        self.max_filesize = max_filesize;
+        self
+    }
+
+    /// Sets how many levels of subdirectories are searched with `-r` (`--max-depth`): `Some(0)` for only the
+    /// files directly in the directory, `None` for no limit.
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Config {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets whether `-r` also searches the files and directories matched by the `.gitignore` and `.mgrepignore`
+    /// files under the directory, which are skipped by default (`--no-ignore`).
+    pub fn with_no_ignore(mut self, no_ignore: bool) -> Config {
+        self.no_ignore = no_ignore;
+        self
+    }
+
+    /// Sets whether `-r` descends into the symlinks to directories under the directory (`--follow`), which are
+    /// skipped by default. Each directory is still searched once, so a link back up the tree doesn't loop.
+    pub fn with_follow(mut self, follow: bool) -> Config {
+        self.follow = follow;
+        self
+    }
+
+    /// Sets whether `-r` also searches the hidden files and directories (whose name starts with `.`) under the
+    /// directory, which are skipped by default, like ripgrep does (`--hidden`).
+    pub fn with_hidden(mut self, hidden: bool) -> Config {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Sets whether the files and directories that can't be read (e.g. permission denied) while searching many
+    /// files are skipped silently (`-s`, `--no-messages`) instead of reported on stderr (`--report-errors`, the
+    /// default). They're left out of `Summary::errors` too.
+    pub fn with_no_messages(mut self, no_messages: bool) -> Config {
+        self.no_messages = no_messages;
+        self
+    }
+
+    /// Sets the order of the files searched with `-r`, `None` to keep the order they're found in (`--sort`).
+    pub fn with_sort(mut self, sort: Option<SortBy>) -> Config {
+        self.sort = sort;
+        self
+    }
+
+    /// Sets how the files detected as binary are searched (`--binary-files`, `-a`).
+    pub fn with_binary_files(mut self, binary_files: BinaryFiles) -> Config {
+        self.binary_files = binary_files;
+        self
+    }
+
+    /// Sets whether the query is matched literally, even with `-e` (`-F`).
+    pub fn with_fixed_strings(mut self, fixed_strings: bool) -> Config {
+        self.fixed_strings = fixed_strings;
+        self
+    }
+
+    /// Sets how the patterns are matched (`--engine`): literally, as regexes, or each as a regex only if it
+    /// contains a regex metacharacter (`.[]()*+?^$|\{}`). `-F` still wins, and `Literal` wins over `-e`.
+    /// With `None`, the default, the patterns are regexes only with `-e`.
+    pub fn with_engine(mut self, engine: Option<Engine>) -> Config {
+        self.engine = engine;
+        self
+    }
+
+    /// Sets which capture group of each regex match is highlighted, and printed by `-o` (`--capture`), e.g. 2 for
+    /// the number after the dash in `(\d+)-(\d+)`. The default, 0, is the whole match. Lines are still selected
+    /// by the whole pattern, even where the group doesn't take part in the match.
+    pub fn with_capture(mut self, capture: usize) -> Config {
+        self.capture = capture;
+        self
+    }
+
+    /// Sets whether the query and the lines are normalized to Unicode NFC before being compared (`--normalize`),
+    /// so `é` matches both its precomposed (U+00E9) and decomposed (`e` + U+0301) forms. Lines are still
+    /// printed as they are.
+    pub fn with_normalize(mut self, normalize: bool) -> Config {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Sets whether filenames are terminated by a NUL byte, e.g. for `xargs -0` (`-Z`).
+    pub fn with_null_data(mut self, null_data: bool) -> Config {
+        self.null_data = null_data;
+        self
+    }
+
+    /// Sets whether the input and output records are separated by NUL bytes instead of newlines (`-z`).
+    pub fn with_null_data_input(mut self, null_data_input: bool) -> Config {
+        self.null_data_input = null_data_input;
+        self
+    }
+
+    /// Sets whether each match is prefixed by the column where it starts (`--column`).
+    pub fn with_column(mut self, column: bool) -> Config {
+        self.column = column;
+        self
+    }
+
+    /// Sets whether the printed lines are stripped of their leading and trailing whitespace (`--trim`).
+    /// Lines are still searched whole.
+    pub fn with_trim(mut self, trim: bool) -> Config {
+        self.trim = trim;
+        self
+    }
+
+    /// Sets after how many characters the printed lines are cut (`--max-columns`), e.g. for minified files.
+    /// The whole line is still searched, and the number of characters left out is printed instead of them.
+    pub fn with_max_columns(mut self, max_columns: Option<usize>) -> Config {
+        self.max_columns = max_columns;
+        self
+    }
+
+    /// Sets whether consecutive identical matching lines are printed only once, like `uniq` (`--dedupe`).
+    /// They're still counted as selected lines.
+    pub fn with_dedupe(mut self, dedupe: bool) -> Config {
+        self.dedupe = dedupe;
+        self
+    }
+
+    /// Sets whether every line is printed, with the matches of the selected ones highlighted (`--passthru`).
+    /// Only the selected lines are counted, and decide the exit status.
+    pub fn with_passthru(mut self, passthru: bool) -> Config {
+        self.passthru = passthru;
+        self
+    }
+
+    /// Sets whether each match is printed on its own line as `file:line:column:text` (`--vimgrep`), with the
+    /// whole line and without highlighting, so a line with two matches is printed twice.
+    pub fn with_vimgrep(mut self, vimgrep: bool) -> Config {
+        self.vimgrep = vimgrep;
+        self
+    }
+
+    /// Sets whether blank lines (empty or only whitespace) are left out of the results, even when the query
+    /// matches them, e.g. `.*` (`--skip-blank`).
+    pub fn with_skip_blank(mut self, skip_blank: bool) -> Config {
+        self.skip_blank = skip_blank;
+        self
+    }
+
+    /// Sets whether a summary like `Matched 37 of 2104 files, 512 total matches` is printed on stderr after
+    /// the results (`--stats`).
+    pub fn with_stats(mut self, stats: bool) -> Config {
+        self.stats = stats;
+        self
+    }
+
+    /// Sets the encoding of the searched files (`--encoding`), e.g. `latin1` or `shift_jis`.
+    /// With `None`, files are UTF-8, or UTF-16 if they start with its byte order mark.
+    pub fn with_encoding(mut self, encoding: Option<String>) -> Config {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets whether the searched files are memory-mapped instead of read (`--mmap`). A file that can't be
+    /// mapped (e.g. a pipe) is still read.
+    pub fn with_mmap(mut self, mmap: bool) -> Config {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Sets whether the files ending in `.gz` are decompressed while they're searched (`--search-zip`), e.g.
+    /// rotated logs, instead of being skipped as binary.
+    pub fn with_search_zip(mut self, search_zip: bool) -> Config {
+        self.search_zip = search_zip;
+        self
+    }
+
+    /// Sets the command run on each searched file, with the file's path as argument and its contents on stdin,
+    /// whose output is searched instead (`--pre`), e.g. `zcat` for compressed files.
+    pub fn with_pre(mut self, pre: Option<String>) -> Config {
+        self.pre = pre;
+        self
+    }
+
+    /// Sets how long the search may take before it's stopped with `MgrepError::Timeout` (`--timeout`), e.g. on
+    /// a network filesystem. A `--pre` command running longer than that is killed.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Config {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets whether a recursive search reports how many files it searched on stderr, if it's a terminal (`--progress`).
+    pub fn with_progress(mut self, progress: bool) -> Config {
+        self.progress = progress;
+        self
+    }
+
+    /// Sets whether the files under the directory are only listed, as `-r` would search them (`--files`).
+    pub fn with_files(mut self, files: bool) -> Config {
+        self.files = files;
+        self
+    }
+
+    /// Sets whether the output is flushed before each line is read, e.g. for `tail -f` (`--line-buffered`).
+    pub fn with_line_buffered(mut self, line_buffered: bool) -> Config {
+        self.line_buffered = line_buffered;
+        self
+    }
+
+    /// Sets whether the matches of each file are grouped under its name, with `-r` (`--heading`).
+    pub fn with_heading(mut self, heading: bool) -> Config {
+        self.heading = heading;
+        self
+    }
+
+    /// Sets whether the matches of each file are separated from the previous file's by a blank line, with many
+    /// files, while still prefixed by the filename (`--separate-files`). `--heading` separates them too.
+    pub fn with_separate_files(mut self, separate_files: bool) -> Config {
+        self.separate_files = separate_files;
+        self
+    }
+
+    /// Sets whether the matching lines and counts are prefixed by their filename: `Some(true)` even for a single
+    /// file (`-H`), `Some(false)` not even for many (`--no-filename`), `None` for only when there are many.
+    pub fn with_filename(mut self, with_filename: Option<bool>) -> Config {
+        self.with_filename = with_filename;
+        self
+    }
+
+    /// Sets whether the query must be at the start of the line (`--line-start`).
+    pub fn with_anchor_start(mut self, anchor_start: bool) -> Config {
+        self.anchor_start = anchor_start;
+        self
+    }
+
+    /// Sets whether the query must be at the end of the line (`--line-end`).
+    pub fn with_anchor_end(mut self, anchor_end: bool) -> Config {
+        self.anchor_end = anchor_end;
+        self
+    }
+
+    /// Sets whether the query must match the whole line, not only a part of it (`-x`, `--line-regexp`): a
+    /// literal query must equal the line (case-folded with `-i`), and a regex must match all of it.
+    pub fn with_line_regexp(mut self, line_regexp: bool) -> Config {
+        self.line_regexp = line_regexp;
+        self
+    }
+
+    /// Sets whether the whole input is searched at once, so that a match can span several lines
+    /// (`-U`, `--multiline`). A regex `^` and `$` still match at the start and end of each line.
+    pub fn with_multiline(mut self, multiline: bool) -> Config {
+        self.multiline = multiline;
+        self
+    }
+
+    /// Sets whether a regex `.` also matches line breaks in a multiline search (`--multiline-dotall`).
+    pub fn with_multiline_dotall(mut self, multiline_dotall: bool) -> Config {
+        self.multiline_dotall = multiline_dotall;
+        self
+    }
+
+    /// Sets the text that replaces every match in the printed lines (`-R`).
+    pub fn with_replace(mut self, replace: Option<String>) -> Config {
+        self.replace = replace;
+        self
+    }
+
+    /// Sets the file the results are written to, instead of stdout (`--output`).
+    pub fn with_output(mut self, output: Option<String>) -> Config {
+        self.output = output;
+        self
+    }
+
+    /// Get the query string: the first positional argument (see `positionals`), wherever the flags are.
+    /// But not before checking whether the args contain a help.
+    ///
+    /// # Arguments
+    /// - `args`: A slice of strings representing command line arguments.
+    ///
+    /// # Returns
+    /// - `Ok((index, arg))` if get a query string, with its index in `args`.
+    /// - `exit(0)` if get the help expression, since asking for help is not an error.
+    /// - `Err(MgrepError::MissingQuery)` if didn't get the previous values.
+    /// - `Err(MgrepError::EmptyQuery)` if the query is empty (or only whitespace), since it would select
+    ///   every line, unless `--allow-empty` is given.
+    fn get_query(args: &[String]) -> Result<(usize, &str), MgrepError> {
+        if Config::has_flag(args, "-h", "--help") {
+            println!("{}", Config::help_message());
+            std::process::exit(0);
+        }
+
+        let (index, query) = Config::positionals(args)
+            .next()
+            .ok_or(MgrepError::MissingQuery)?;
+
+        if query.trim().is_empty() && !args.iter().any(|arg| arg == "--allow-empty") {
+            return Err(MgrepError::EmptyQuery);
+        }
+
+        Ok((index, query.as_str()))
+    }
+
+    /// The positional arguments, i.e. neither flags nor the values they consume, with their index in `args`.
+    ///
+    /// # Note
+    /// - A query starting with `-` is taken for a flag, so it has to be written differently (e.g. `[-]x` with `-e`)
+    ///   or read from a pattern file (`-f`).
+    fn positionals(args: &[String]) -> impl Iterator<Item = (usize, &String)> {
+        args.iter().enumerate().filter(|(index, arg)| {
+            let is_flag_value = index
+                .checked_sub(1)
+                .is_some_and(|previous| VALUE_FLAGS.contains(&args[previous].as_str()));
+
+            !is_flag_value && !arg.starts_with('-')
+        })
+    }
+
+    /// Splits the clusters of short flags into separate flags, like grep: `-in` becomes `-i -n`, and `-vm 2`
+    /// becomes `-v -m 2`. The rest of a cluster after a flag taking a value is that value: `-A1` becomes
+    /// `-A 1`, and `-vm2` becomes `-v -m 2`.
+    ///
+    /// # Returns
+    /// - `Ok(args)`: The arguments, with each cluster replaced by its flags. `-ni` (`--no-ignore-case`) and the
+    ///   values of flags (e.g. `-R -ab-`) are kept as they are.
+    /// - `Err(MgrepError::Usage)`: If a cluster contains a letter that isn't a short flag (see `SHORT_FLAGS`)
+    ///   before any flag taking a value.
+    fn split_clusters(args: Vec<String>) -> Result<Vec<String>, MgrepError> {
+        let mut split: Vec<String> = Vec::with_capacity(args.len());
+
+        for arg in args {
+            let is_flag_value = split
+                .last()
+                .is_some_and(|previous| VALUE_FLAGS.contains(&previous.as_str()));
+            let cluster = arg
+                .strip_prefix('-')
+                .filter(|letters| letters.chars().count() > 1 && !letters.starts_with('-'));
+
+            let Some(letters) = cluster.filter(|_| !is_flag_value && arg != "-ni") else {
+                split.push(arg);
+                continue;
+            };
+
+            for (index, letter) in letters.char_indices() {
+                let flag = format!("-{letter}");
+
+                if VALUE_FLAGS.contains(&flag.as_str()) {
+                    split.push(flag);
+                    // The value is attached (e.g. `-A1`), or the next argument when the flag ends the cluster.
+                    let value = &letters[index + letter.len_utf8()..];
+                    if !value.is_empty() {
+                        split.push(value.to_string());
+                    }
+                    break;
+                } else if !SHORT_FLAGS.contains(&flag.as_str()) {
+                    return Err(MgrepError::Usage(format!(
+                        "invalid flag {flag} in the combined flags {arg}"
+                    )));
+                }
+                split.push(flag);
+            }
+        }
+
+        Ok(split)
+    }
+
+    /// Checks that no two flags of `CONFLICTS` are given together.
+    ///
+    /// # Returns
+    /// - `Ok(())` if there's no conflict.
+    /// - `Err(MgrepError::Usage)` naming the first conflicting pair, e.g.
+    ///   `-o (--only-matching) and -v (--invert-match) can't be used together`.
+    fn check_conflicts(args: &[String]) -> Result<(), MgrepError> {
+        let name = |(short, long): Flag| {
+            if short == long {
+                long.to_string()
+            } else {
+                format!("{short} ({long})")
+            }
+        };
+
+        // A flag with a value may also be given as `--replace=TEXT`.
+        let given = |short: &str, long: &str| {
+            Config::has_flag(args, short, long)
+                || args.iter().any(|arg| {
+                    arg.strip_prefix(long)
+                        .is_some_and(|rest| rest.starts_with('='))
+                })
+        };
+        match CONFLICTS
+            .iter()
+            .find(|((short, long), (other_short, other_long))| {
+                given(short, long) && given(other_short, other_long)
+            }) {
+            Some(&(flag, other)) => Err(MgrepError::Usage(format!(
+                "{} and {} can't be used together",
+                name(flag),
+                name(other)
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Gets the patterns given with `-p` (`--pattern`), which can be repeated.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<String>)`: The value of every `-p`, in order (none if the flag wasn't given).
+    /// - `Err(MgrepError::EmptyQuery)`: If one of them is empty (or only whitespace), since it would select
+    ///   every line, unless `--allow-empty` is given.
+    fn get_pattern_args(args: &[String]) -> Result<Vec<String>, MgrepError> {
+        let patterns = Config::get_values(args, "-p", "--pattern");
+
+        if patterns.iter().any(|pattern| pattern.trim().is_empty())
+            && !args.iter().any(|arg| arg == "--allow-empty")
+        {
+            return Err(MgrepError::EmptyQuery);
+        }
+
+        Ok(patterns.into_iter().map(str::to_string).collect())
+    }
+
+    /// Reads the patterns to search for from a file, one per line.
+    ///
+    /// # Arguments
+    /// - `path`: The path of the pattern file given to `-f` (`--file`, `--patterns-from`), or `-` for stdin.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<String>)`: Every non-blank line of the file.
+    /// - `Err(MgrepError::FileRead)`: If the file can't be read.
+    /// - `Err(MgrepError::Io)`: If stdin can't be read.
+    fn get_pattern_file(path: &str) -> Result<Vec<String>, MgrepError> {
+        let contents = if path == "-" {
+            io::read_to_string(io::stdin())?
+        } else {
+            fs::read_to_string(path).map_err(|err| MgrepError::FileRead(err, path.into()))?
+        };
+
+        Ok(contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    // Whether the results come from several files (`-r`, or an expanded glob), so each names its file.
+    fn many_files(&self) -> bool {
+        self.recursive || matches!(self.input, InputType::FilePaths(_))
+    }
+
+    // Whether the occurrences of the query are counted instead of the lines: with `--count-matches`, or `-c`
+    // with `-o` (grep's `-oc` idiom), which would otherwise print each match.
+    fn counts_matches(&self) -> bool {
+        self.count_matches || (self.count && self.only_matching)
+    }
+
+    // Whether the lines and counts are prefixed by their filename: as forced by `-H` or `--no-filename`, else
+    // only when there are many files.
+    fn shows_filename(&self) -> bool {
+        self.with_filename.unwrap_or_else(|| self.many_files())
+    }
+
+    // The word characters of `-w`, or `None` if matches don't have to be whole words.
+    fn word_chars(&self) -> Option<WordChars> {
+        match (self.word, self.ascii_word) {
+            (false, _) => None,
+            (true, false) => Some(WordChars::Unicode),
+            (true, true) => Some(WordChars::Ascii),
+        }
+    }
+
+    /// Reduces the patterns to the single query matched by the search functions.
+    ///
+    /// # Returns
+    /// - `(query, regex)`: A single pattern is returned as is, with `self.regex` (unless `-F` wins over it).
+    ///   Several patterns are combined into one regular expression matching any of them (literal patterns
+    ///   are escaped first), so `regex` is then always `true`.
+    ///   With `--line-start`/`--line-end` the query is anchored with `^`/`$` too (so it's then also a regex),
+    ///   and the matched spans, highlighting included, are only the anchored ones. `-x` anchors both ends.
+    fn combined_query(&self) -> (Cow<'_, str>, bool) {
+        let (query, regex) = self.combined_patterns();
+        let anchor_start = self.anchor_start || self.line_regexp;
+        let anchor_end = self.anchor_end || self.line_regexp;
+
+        if !anchor_start && !anchor_end {
+            return (query, regex);
+        }
+
+        let query = if regex {
+            query
+        } else {
+            Cow::Owned(regex::escape(&query))
+        };
+        let start = if anchor_start { "^" } else { "" };
+        let end = if anchor_end { "$" } else { "" };
+
+        (Cow::Owned(format!("{start}(?:{query}){end}")), true)
+    }
+
+    /// Checks that the group of `--capture` is one of the query's: a query with fewer groups, or that isn't a
+    /// regular expression, can't highlight it.
+    ///
+    /// # Returns
+    /// - `Ok(())` if there's no group to highlight (0), or the query has it.
+    /// - `Err(MgrepError::Usage)` if a group is given for a literal query.
+    /// - `Err(MgrepError::InvalidValue)` if the query has fewer groups.
+    /// - `Err(MgrepError::InvalidRegex)` if the query isn't a valid regular expression.
+    fn check_capture(&self) -> Result<(), MgrepError> {
+        if self.capture == 0 {
+            return Ok(());
+        }
+
+        let (query, regex) = self.combined_query();
+        if !regex {
+            return Err(MgrepError::Usage(
+                "--capture needs a regular expression (-e)".to_string(),
+            ));
+        }
+
+        let groups = build_regex(&query, false, None)?.captures_len() - 1;
+        if self.capture > groups {
+            return Err(MgrepError::invalid_value(
+                "--capture",
+                &self.capture.to_string(),
+                &format!("a group of the pattern, from 0 to {groups}"),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Same as `combined_query`, without the anchors.
+    fn combined_patterns(&self) -> (Cow<'_, str>, bool) {
+        if let [query] = &self.patterns[..] {
+            return (Cow::Borrowed(query), self.is_regex(query));
+        }
+
+        // No pattern at all (e.g. an empty pattern file) must match nothing.
+        if self.patterns.is_empty() {
+            return (Cow::Borrowed(r"[^\s\S]"), true);
+        }
+
+        let mut alternatives: Vec<String> = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                if self.is_regex(pattern) {
+                    pattern.clone()
+                } else {
+                    regex::escape(pattern)
+                }
+            })
+            .collect();
+
+        // The leftmost alternative wins, so longer literals go first (e.g. `category` before `cat`).
+        if !self.patterns.iter().any(|pattern| self.is_regex(pattern)) {
+            alternatives.sort_by_key(|alternative| std::cmp::Reverse(alternative.len()));
+        }
+
+        let combined: Vec<String> = alternatives
+            .iter()
+            .map(|alternative| format!("(?:{alternative})"))
+            .collect();
+
+        (Cow::Owned(combined.join("|")), true)
+    }
+
+    // Whether `pattern` is a regex: `-F` (`--fixed-strings`) takes precedence over `--engine`, which takes
+    // precedence over `-e` (`--regex`), except that `auto` makes every pattern a regex with `-e`.
+    fn is_regex(&self, pattern: &str) -> bool {
+        if self.fixed_strings {
+            return false;
+        }
+
+        match self.engine {
+            None => self.regex,
+            Some(Engine::Literal) => false,
+            Some(Engine::Regex) => true,
+            Some(Engine::Auto) => {
+                self.regex || pattern.contains(|c| REGEX_METACHARACTERS.contains(c))
+            }
+        }
+    }
+
+    /// Determines the case sensitivity for the command based on the provided arguments and environment.
+    ///
+    /// The function first checks command line arguments for `-i` (`--ignore-case`) or `-ni` (`--no-ignore-case`).
+    /// If neither is specified, `-S` (`--smart-case`) ignores case only when the query is all lowercase.
+    /// Otherwise, it checks the `IGNORE_CASE` environment variable: `0`, `false` or an empty value keep the search
+    /// case-sensitive, any other value ignores case.
+    ///
+    /// # Arguments
+    /// - `args`: A slice of strings representing command line arguments.
+    /// - `query`: The query string, inspected for uppercase characters in smart-case mode.
+    /// - `env_value`: The value of the `IGNORE_CASE` environment variable, `None` if it isn't set.
+    ///
+    /// # Returns
+    /// - `true` if case sensitivity should be ignored (case-insensitive mode).
+    /// - `false` if case sensitivity should be respected (case-sensitive mode).
+    ///
+    /// # Note
+    /// - The `-ni` or `--no-ignore-case` argument takes precedence over the `IGNORE_CASE` environment variable.
+    /// - Explicit `-i` or `-ni` arguments override `-S`.
+    fn get_ignore_case(args: &[String], query: &str, env_value: Option<&str>) -> bool {
+        let ignore_case_flag = args.iter().any(|arg| arg == "-i" || arg == "--ignore-case");
+        let no_ignore_case_flag = args
+            .iter()
+            .any(|arg| arg == "-ni" || arg == "--no-ignore-case");
+        let smart_case_flag = Config::has_flag(args, "-S", "--smart-case");
+
+        // Check command line flags first for explicit setting
+        if no_ignore_case_flag {
+            false
+        } else if ignore_case_flag {
+            true
+        } else if smart_case_flag {
+            // A query with uppercase characters is assumed to be meant literally.
+            !query.chars().any(char::is_uppercase)
+        } else {
+            // If no flags are specified, default to the environment variable
+            env_value.is_some_and(|value| {
+                !(value.is_empty() || value == "0" || value.eq_ignore_ascii_case("false"))
+            })
+        }
+    }
+
+    /// Determines whether filenames are printed, from `-H` (`--with-filename`) and `--no-filename`.
+    ///
+    /// # Returns
+    /// - `Some(true)` or `Some(false)` for the last of these flags, so it overrides the ones before.
+    /// - `None` if neither was given, leaving it to the number of files searched.
+    fn get_with_filename(args: &[String]) -> Option<bool> {
+        args.iter().rev().find_map(|arg| match arg.as_str() {
+            "-H" | "--with-filename" => Some(true),
+            "--no-filename" => Some(false),
+            _ => None,
+        })
+    }
+
+    /// Determines whether unreadable files are reported, from `-s` (`--no-messages`) and `--report-errors`.
+    ///
+    /// # Returns
+    /// - `true` if the last of these flags is `-s` (`--no-messages`), so it overrides the ones before.
+    /// - `false` if it's `--report-errors`, or neither was given.
+    fn get_no_messages(args: &[String]) -> bool {
+        args.iter()
+            .rev()
+            .find_map(|arg| match arg.as_str() {
+                "-s" | "--no-messages" => Some(true),
+                "--report-errors" => Some(false),
+                _ => None,
+            })
+            .unwrap_or(false)
+    }
+
+    /// Determines when to highlight the matches, based on the provided arguments and environment.
+    ///
+    /// # Arguments
+    /// - `args`: A slice of strings representing command line arguments.
+    ///
+    /// # Returns
+    /// - `Ok(ColorChoice)`: The value of `--color`, else `never` if the `NO_COLOR` environment variable is
+    ///   present (whatever its value), else `auto`.
+    /// - `Err(MgrepError::InvalidValue)`: If the value of `--color` is invalid.
+    ///
+    /// # Note
+    /// - An explicit `--color` (e.g. `--color=always`) takes precedence over `NO_COLOR` (https://no-color.org).
+    fn get_color(args: &[String], no_color: bool) -> Result<ColorChoice, MgrepError> {
+        match Config::get_value(args, "--color", "--color") {
+            Some(value) => ColorChoice::parse(value),
+            None if no_color => Ok(ColorChoice::Never),
+            None => Ok(ColorChoice::Auto),
+        }
+    }
+
+    /// Checks whether a boolean flag was given, in either its short or long form.
+    ///
+    /// # Arguments
+    /// - `args`: A slice of strings representing command line arguments.
+    /// - `short`: The short form of the flag (e.g. `-e`).
+    /// - `long`: The long form of the flag (e.g. `--regex`).
+    ///
+    /// # Returns
+    /// - `true` if any argument is exactly `short` or `long`.
+    /// - `false` otherwise.
+    fn has_flag(args: &[String], short: &str, long: &str) -> bool {
+        args.iter().any(|arg| arg == short || arg == long)
+    }
+
+    /// Gets the value of a flag, given either as `-A 2`, `--after-context 2` or `--after-context=2`.
+    ///
+    /// # Arguments
+    /// - `args`: A slice of strings representing command line arguments.
+    /// - `short`: The short form of the flag (e.g. `-A`).
+    /// - `long`: The long form of the flag (e.g. `--after-context`).
+    ///
+    /// # Returns
+    /// - `Some(value)` for the last occurrence of the flag, so later flags override earlier ones.
+    /// - `None` if the flag wasn't given (or was given last without a value).
+    fn get_value<'a>(args: &'a [String], short: &str, long: &str) -> Option<&'a str> {
+        let mut value = None;
+
+        for (index, arg) in args.iter().enumerate() {
+            if arg == short || arg == long {
+                value = args.get(index + 1).map(String::as_str);
+            } else if let Some(inline) = arg
+                .strip_prefix(long)
+                .and_then(|rest| rest.strip_prefix('='))
+            {
+                value = Some(inline);
+            }
+        }
+
+        value
+    }
+
+    /// Gets the values of every occurrence of a repeatable flag (see `get_value`).
+    ///
+    /// # Returns
+    /// The values in the order they were given, empty if the flag wasn't given.
+    fn get_values<'a>(args: &'a [String], short: &str, long: &str) -> Vec<&'a str> {
+        let mut values = Vec::new();
+
+        for (index, arg) in args.iter().enumerate() {
+            if arg == short || arg == long {
+                values.extend(args.get(index + 1).map(String::as_str));
+            } else if let Some(inline) = arg
+                .strip_prefix(long)
+                .and_then(|rest| rest.strip_prefix('='))
+            {
+                values.push(inline);
+            }
+        }
+
+        values
+    }
+
+    /// Gets the numeric value of a flag (see `get_value`).
+    ///
+    /// # Returns
+    /// - `Ok(Some(number))` if the flag was given with a valid non-negative integer.
+    /// - `Ok(None)` if the flag wasn't given.
+    /// - `Err(MgrepError::InvalidValue)` if the value isn't a valid number.
+    fn get_number(args: &[String], short: &str, long: &str) -> Result<Option<usize>, MgrepError> {
+        Config::get_value(args, short, long)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| MgrepError::invalid_value(long, value, "a number"))
+            })
+            .transpose()
+    }
+
+    /// Parses the value of `--max-filesize`: a number of bytes, optionally followed by a `K`, `M` or `G`
+    /// suffix (powers of 1024, in either case).
+    ///
+    /// # Returns
+    /// - `Ok(bytes)` for a valid size, e.g. `512`, `10K` or `2M`.
+    /// - `Err(MgrepError::InvalidValue)` for anything else (e.g. `5Z`), or a size that doesn't fit in 64 bits.
+    fn parse_size(value: &str) -> Result<u64, MgrepError> {
+        let (digits, multiplier) = match value.char_indices().next_back() {
+            Some((index, 'k' | 'K')) => (&value[..index], 1 << 10),
+            Some((index, 'm' | 'M')) => (&value[..index], 1 << 20),
+            Some((index, 'g' | 'G')) => (&value[..index], 1 << 30),
+            _ => (value, 1),
+        };
+
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|size| size.checked_mul(multiplier))
+            .ok_or_else(|| {
+                MgrepError::invalid_value("--max-filesize", value, "e.g. 512, 10K, 2M or 1G")
+            })
+    }
+
+    /// Parses the value of `--timeout`: a positive number followed by `ms`, `s` or `m` (a bare number is in
+    /// seconds).
+    ///
+    /// # Returns
+    /// - `Ok(duration)` for a valid duration, e.g. `500ms`, `5s` or `2m`.
+    /// - `Err(MgrepError::InvalidValue)` for anything else (e.g. `5h` or `0s`).
+    fn parse_duration(value: &str) -> Result<Duration, MgrepError> {
+        let (digits, unit) = if let Some(digits) = value.strip_suffix("ms") {
+            (digits, Duration::from_millis(1))
+        } else if let Some(digits) = value.strip_suffix('s') {
+            (digits, Duration::from_secs(1))
+        } else if let Some(digits) = value.strip_suffix('m') {
+            (digits, Duration::from_secs(60))
+        } else {
+            (value, Duration::from_secs(1))
+        };
+
+        digits
+            .parse::<u32>()
+            .ok()
+            .filter(|&amount| amount > 0)
+            .and_then(|amount| unit.checked_mul(amount))
+            .ok_or_else(|| MgrepError::invalid_value("--timeout", value, "e.g. 500ms, 5s or 2m"))
+    }
+
+    /// Gets the number of threads used to search many files, from `-j` (`--threads`).
+    ///
+    /// # Returns
+    /// - `Ok(threads)`: The given number, or the number of logical CPUs when the flag is absent.
+    /// - `Err(MgrepError::InvalidValue)`: If the value isn't a positive integer.
+    fn get_threads(args: &[String]) -> Result<usize, MgrepError> {
+        match Config::get_number(args, "-j", "--threads")? {
+            Some(0) => Err(MgrepError::invalid_value("--threads", "0", "at least 1")),
+            Some(threads) => Ok(threads),
+            None => Ok(Config::default_threads()),
+        }
+    }
+
+    // One thread per logical CPU.
+    fn default_threads() -> usize {
+        thread::available_parallelism().map_or(1, |threads| threads.get())
+    }
+
+    /// Distinguishes between file path and command and returns the InputType.
+    ///
+    /// # Arguments
+    /// - `args` A slice of strings representing command line arguments.
+    /// - `query_index` The index of the query in `args` (`None` with `-f`), which is never an input.
+    /// - `stdin_is_terminal` Whether stdin is a terminal rather than a pipe or a file.
+    ///
+    /// - `Ok(InputType::FilePath(String))`: Returns a `FilePath` variant of `InputType` if one of the positional
+    ///   arguments after the query names a file or directory that exists, or is a glob matching a single file.
+    /// - `Ok(InputType::FilePaths(Vec<String>))`: If instead one of them is a glob matching several files
+    ///   (see `glob::expand`), which the shell didn't expand (e.g. `cmd.exe` on Windows).
+    /// - `Ok(InputType::LiteralInput(String))`: Returns a `LiteralInput` variant of `InputType` if no path is
+    ///   detected, or if stdin is asked for explicitly with `-` (`--stdin`). It reads the entire input from
+    ///   stdin, assuming it to be a direct text input.
+    /// - `Ok(InputType::Stdin)`: Instead of `LiteralInput` with `--line-buffered`, so stdin is only read while
+    ///   it's searched.
+    /// - `Err(MgrepError::Usage)`: Returns an error if the query names an existing path while none of the other
+    ///   arguments does (e.g. `mgrep src/lib.rs fn`, with the arguments swapped), or if there's no path and
+    ///   stdin is a terminal, where reading would wait for typed input, unless `-` asks for it.
+    /// - `Err(MgrepError::Io)`: If there are issues reading from stdin.
+    fn get_input(
+        args: &[String],
+        query_index: Option<usize>,
+        stdin_is_terminal: bool,
+    ) -> Result<InputType, MgrepError> {
+        // A `-` given as the value of a flag (e.g. `-f -`) doesn't ask for stdin.
+        let force_stdin = args.iter().enumerate().any(|(index, arg)| {
+            arg == "--stdin"
+                || (arg == "-"
+                    && !index
+                        .checked_sub(1)
+                        .is_some_and(|previous| VALUE_FLAGS.contains(&args[previous].as_str())))
+        });
+
+        // Flags, the values they consume and the query are never paths.
+        let candidates: Vec<&String> = Config::positionals(args)
+            .filter(|(index, _)| Some(*index) != query_index)
+            .map(|(_, arg)| arg)
+            .collect();
+
+        // Checks if it is a file path.
+        if let Some(arg) = candidates
+            .iter()
+            .find(|arg| !force_stdin && Path::new(arg).exists())
+        {
+            Ok(InputType::FilePath(arg.to_string()))
+        // A glob the shell didn't expand (or that didn't match a literal file) names the files it matches.
+        } else if let Some(paths) = candidates
+            .iter()
+            .filter(|_| !force_stdin)
+            .map(|arg| glob::expand(arg))
+            .find(|paths| !paths.is_empty())
+        {
+            let mut paths: Vec<String> = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            if paths.len() == 1 {
+                Ok(InputType::FilePath(paths.remove(0)))
+            } else {
+                Ok(InputType::FilePaths(paths))
+            }
+        } else if let Some(query) = query_index
+            .map(|index| &args[index])
+            .filter(|query| !force_stdin && !candidates.is_empty() && Path::new(query).exists())
+        {
+            // Searching stdin for a path would silently drop the real query, so the order is reported instead.
+            Err(MgrepError::Usage(format!(
+                "'{query}' names an existing path but is taken as the query, while '{}' doesn't exist: \
+                the query comes first (e.g. mgrep \"{}\" {query}), add - to search stdin",
+                candidates[0], candidates[0]
+            )))
+        // Without a pipe, reading stdin would seemingly hang until end of file (Ctrl-D) is typed.
+        } else if stdin_is_terminal && !force_stdin {
+            Err(MgrepError::Usage(
+                "no file to search and nothing piped to stdin: give a PATH (e.g. mgrep \"QUERY\" src -r), \
+                pipe the input (e.g. cat app.log | mgrep \"QUERY\"), or add - to type it"
+                    .to_string(),
+            ))
+        // A live stream (e.g. `tail -f`) never ends, so it can't be read whole first.
+        } else if Config::has_flag(args, "--line-buffered", "--line-buffered") {
+            Ok(InputType::Stdin)
+        // Understands that it is a command.
+        } else {
+            let mut input_line = String::new();
+            io::stdin().read_to_string(&mut input_line)?;
+
+            Ok(InputType::LiteralInput(
+                input_line.trim_matches('"').to_string(),
+            ))
+        }
+    }
+
+    /// This function is used when get_query() realize that it
+    /// contains an arg by calling the help.
+    fn help_message() -> String {
+        "Usage:
+        File Path:
+        cargo run -- [\"QUERY\"] [PATH] [EXPRESSION]
+
+        String Literal One Line:
+        echo \"The literal string go here\" | cargo run -- [\"QUERY\"] [EXPRESSION]
+
+        String Literal Multiple Lines:
+        cat << EOF | cargo run -- [\"QUERY\"] [EXPRESSION]
+        The literal string
+        go
+        here
+        EOF
+
+        Help:
+        cargo run -- [EXPRESSION]
+
+        Expressions:
+        -i, --ignore-case        ignore case sensitive in search
+        -ni, --no-ignore-case    don't ignore case sensitive in search
+        -S, --smart-case         ignore case sensitive only if the query is all lowercase
+        -e, --regex              interpret the query as a regular expression
+        -F, --fixed-strings      match the query literally, even with -e (same as --engine=literal)
+        --engine=ENGINE          literal, regex, or auto: a regex only if the query has .[]()*+?^$|\\{}
+        --capture=N              with -e, highlight (and print with -o) only group N of each match
+        --normalize              match precomposed and decomposed accents alike (Unicode NFC)
+        -n, --line-number        prefix each matching line with its line number
+        -b, --byte-offset        prefix each matching line with the byte offset of its start (of each match with -o)
+        --column                 prefix each matching line with the column of its first match
+        --trim                   print lines without their leading and trailing whitespace
+        --max-columns=N          print only the first N characters of long lines (the whole line is searched)
+        --dedupe                 print consecutive identical matching lines only once
+        --passthru               print every line, highlighting the matches (e.g. to read a whole log)
+        --skip-blank             never select empty or whitespace-only lines, even if the query matches them
+        --stats                  print how many files were searched and matched, and the matches, on stderr
+        --heading                with -r, print each file's name once above its matches instead of as a prefix
+        --separate-files         print a blank line between the matches of different files
+        -H, --with-filename      prefix each matching line with its filename, even when searching one file
+        --no-filename            never prefix the matching lines with their filename, even with -r
+        -r, --recursive          search every file under the given directory
+        --files                  list the files -r would search under the given directory (no QUERY argument)
+        -v, --invert-match       select the lines that do not match
+        -c, --count              print only the number of selected lines (of matches, with -o)
+        --total                  with -c, print only the number of selected lines in all the files
+        --ratio                  with -c, also print how many lines were searched and the percentage selected
+        --include-zero           with -c over several files, also print the files without a match (file:0)
+        --count-matches          print only the number of occurrences of the query, even several per line
+        -w, --word-regexp        match the query only as a whole word
+        -x, --line-regexp        match the query only as the whole line
+        -U, --multiline          search the whole input at once, so matches can span lines
+        --multiline-dotall       with -U, let . match line breaks too
+        --ascii-word             with -w, only ASCII letters, digits and _ are word characters
+        --line-start             match the query only at the start of a line
+        --line-end               match the query only at the end of a line (with --line-start: the whole line)
+        -R, --replace TEXT       print the matching lines with every match replaced by TEXT
+        --allow-empty            accept an empty (or blank) QUERY, which matches every line
+        -f, --file FILE          read the patterns from FILE, one per line (no QUERY argument)
+        --patterns-from=FILE     same as -f; with -, read the patterns from stdin (the text is then in a FILE)
+        -p, --pattern PATTERN    search for PATTERN, can be repeated to select lines matching any (no QUERY argument)
+        -A, --after-context N    print N lines of context after each match
+        -B, --before-context N   print N lines of context before each match
+        -C, --context N          print N lines of context before and after each match
+        --group-separator SEP    print SEP between groups of context lines instead of --
+        --no-group-separator     print nothing between groups of context lines
+        --json                   print each matching line as a JSON object
+        --vimgrep                print each match as file:line:column:line, for Vim's quickfix list
+        --color=WHEN             highlight matches: auto (default, only on a terminal), always or never
+        --highlight-color=COLOR  red (default), green, yellow, blue, magenta, cyan or bold
+        --progress               with -r, show how many files were searched so far on stderr (only on a terminal)
+        -j, --threads N          search up to N files in parallel (default: number of CPUs)
+        -m, --max-count N        stop reading a file after N matching lines
+        --max-total=N            stop the whole search after N matching lines, over all the files
+        --include=GLOB           with -r, search only the files whose name matches GLOB (repeatable)
+        --max-filesize=SIZE      with -r, skip the files larger than SIZE bytes (K, M and G suffixes allowed)
+        --max-depth=N            with -r, descend at most N levels of subdirectories (0: only the directory's files)
+        --no-ignore              with -r, also search the files matched by .gitignore and .mgrepignore files
+        --follow                 with -r, descend into symlinked directories too (skipped by default)
+        --hidden                 with -r, search the hidden files and directories too (skipped by default)
+        -s, --no-messages        with -r, skip the unreadable files and directories without reporting them
+        --report-errors          with -r, report the unreadable files and directories on stderr (default)
+        --sort=ORDER             with -r, search the files by path or modified (oldest first), not as found
+        --exclude=GLOB           with -r, skip the files whose name matches GLOB, even if included (repeatable)
+        --encoding=NAME          read the files in encoding NAME (e.g. latin1, shift_jis) instead of UTF-8
+        --mmap                   memory-map the files instead of reading them (faster on very large files)
+        --search-zip             search the decompressed contents of the gzip files (ending in .gz)
+        --pre COMMAND            search the output of COMMAND run on each file (e.g. zcat) instead of the file
+        --timeout=DURATION       stop with an error if the search takes longer than DURATION (e.g. 500ms, 5s, 2m)
+        --binary-files=TYPE      binary (default: only report a match), without-match (skip) or text
+        -a, --text               search binary files as text, like --binary-files=text
+        -l, --files-with-matches print only the names of the files with a match
+        -L, --files-without-match print only the names of the files without a match
+        -o, --only-matching      print only the matched parts of the lines, one per line
+        -z, --null-data          read and print records terminated by a NUL byte instead of lines
+        -Z, --null               follow each filename with a NUL byte instead of ':' (or a newline with -l/-L)
+        -O, --output FILE        write the results to FILE instead of stdout (not highlighted by default)
+        --line-buffered          print each match as soon as it's found, e.g. for tail -f (stdin is read as it arrives)
+        -q, --quiet              print nothing, stop at the first match and only set the exit status
+        -, --stdin               search stdin, even if an argument names an existing file
+        -h, --help               display this help and exit
+        
+        Environment Variable Usage:
+        IGNORE_CASE=1            ignore case sensitive in search (0, false or empty keep it case-sensitive)
+        NO_COLOR=1               don't highlight matches, unless --color is given
+        MGREP_COLORS=fn=35:ln=32 colors of the filenames (fn), line numbers (ln), byte offsets (bn) and
+                                 separators (se), like GREP_COLORS
+
+        Exit Status:
+        0 if a line is selected, 1 if no line is selected, 2 if an error occurred"
+            .to_string()
+    }
+}
+
+// What a completed `run` found.
+pub struct Summary {
+    // The number of selected lines, over all the searched files.
+    pub count: usize,
+    // The files that couldn't be searched (as `mgrep: path: error`), which didn't stop the others. Always
+    // empty with `-s`.
+    pub errors: Vec<String>,
+}
+
+/// That's the core function of the program.
+///
+/// # Returns
+/// - `Ok(Summary)`: The number of selected lines, and the files that couldn't be read while searching many.
+/// - `Err(MgrepError)`: If the search couldn't be run at all (e.g. invalid regex, unreadable input file,
+///   output file that can't be created), or took longer than its `--timeout`.
+///
+/// # Note
+/// - In quiet mode (`-q`) nothing is printed and the search stops at the first selected line,
+///   so the returned count is the only result.
+/// - Searches that stop early (`-q`, `-l`, `-L`, `-m`) only count the lines read, so the count is then
+///   only meaningful compared to 0.
+/// - After a `--timeout`, the search is cancelled: it writes nothing more, and stops at its next line.
+pub fn run(config: Config) -> Result<Summary, MgrepError> {
+    let Some(timeout) = config.timeout else {
+        return run_search(config);
+    };
+
+    // The search runs on its own thread, which is left behind if it's too slow: it can be stuck in a read
+    // (e.g. on a network filesystem) that can't be interrupted. It's cancelled, so that once it gets going
+    // again it stops before the next line (or file), and writes nothing more after the error is returned.
+    let cancelled = Arc::clone(&config.cancelled);
+    let (sender, receiver) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        // The receiver may be gone after a timeout.
+        let _ = sender.send(run_search(config));
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => {
+            cancelled.store(true, Ordering::Relaxed);
+            Err(MgrepError::Timeout(timeout))
+        }
+        // The search panicked before sending its result.
+        Err(RecvTimeoutError::Disconnected) => match worker.join() {
+            Err(payload) => panic::resume_unwind(payload),
+            Ok(()) => unreachable!("the search always sends its result"),
+        },
+    }
+}
+
+// The search of `run`, without its timeout.
+fn run_search(mut config: Config) -> Result<Summary, MgrepError> {
+    // No file is read past the lines the whole search selects.
+    if let Some(max_total) = config.max_total {
+        config.max_count = Some(
+            config
+                .max_count
+                .map_or(max_total, |max_count| max_count.min(max_total)),
+        );
+    }
+
+    // Prepared once and shared by every file, so the regex isn't compiled (nor the query lowercased) again for each.
+    let matcher = Matcher::from_config(&config)?;
+    let encoding = config.encoding.as_deref().map(encoding_for).transpose()?;
+
+    // A file is never a terminal, so `--color=auto` doesn't highlight the results written to it.
+    let color = config
+        .color
+        .should_color(config.output.is_none() && io::stdout().is_terminal())
+        .then_some(config.highlight_color);
+
+    // The output (stdout, or the `--output` file) is buffered, and stdout locked once, instead of for every write.
+    let cancelled = Arc::clone(&config.cancelled);
+    let mut out: Box<dyn Write> = match &config.output {
+        Some(path) => Box::new(BufWriter::new(Cancellable {
+            inner: File::create(path).map_err(|err| MgrepError::OutputFile(err, path.into()))?,
+            cancelled,
+        })),
+        None => Box::new(BufWriter::new(Cancellable {
+            inner: io::stdout().lock(),
+            cancelled,
+        })),
+    };
+
+    // See the description in `Config::get_input()`
+    let mut errors = Vec::new();
+    // The number of files searched and of those with a match, for `--stats`, when there's more than one input.
+    let mut file_stats = None;
+
+    let count = match &config.input {
+        // Only the files a recursive search would read are printed, without reading them.
+        InputType::FilePath(path) if config.files => {
+            let files = files_to_search(&config, Path::new(path));
+            let terminator = if config.null_data { '\0' } else { '\n' };
+            for file in &files {
+                write!(out, "{}{terminator}", file.display())?;
+            }
+
+            files.len()
+        }
+        // Every file under the directory is searched, and matches are prefixed by their path.
+        InputType::FilePath(path) if config.recursive && Path::new(path).is_dir() => {
+            let files = files_to_search(&config, Path::new(path));
+            let (total, stats) = search_files(
+                &config,
+                &matcher,
+                color,
+                encoding,
+                &files,
+                &mut out,
+                &mut errors,
+            )?;
+            file_stats = Some(stats);
+
+            total
+        }
+        // The files of an expanded glob are searched the same way.
+        InputType::FilePaths(paths) => {
+            let files: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+            let (total, stats) = search_files(
+                &config,
+                &matcher,
+                color,
+                encoding,
+                &files,
+                &mut out,
+                &mut errors,
+            )?;
+            file_stats = Some(stats);
+
+            total
+        }
+        // Reading it would only fail with the OS's `Is a directory`.
+        InputType::FilePath(path) if Path::new(path).is_dir() => {
+            return Err(MgrepError::IsDirectory(path.into()))
+        }
+        InputType::FilePath(path) => open_file(&config, encoding, Path::new(path))
+            .and_then(|reader| {
+                print_matches(&config, &matcher, color, Some(path), reader, &mut out)
+            })
+            .map_err(|err| MgrepError::FileRead(err, path.into()))?,
+        InputType::LiteralInput(text) => {
+            print_matches(&config, &matcher, color, None, text.as_bytes(), &mut out)?
+        }
+        InputType::Stdin => {
+            print_matches(&config, &matcher, color, None, io::stdin().lock(), &mut out)?
+        }
+    };
+
+    // Each file's count was left out for their sum (see `print_matches`).
+    if sums_counts(&config) {
+        writeln!(out, "{count}")?;
+    }
+
+    out.flush()?;
+
+    // Printed after the results, which are all flushed, so it's never mixed in with them.
+    if config.stats && !config.files {
+        let (searched, matched) = file_stats.unwrap_or((1, usize::from(count > 0)));
+        eprintln!("Matched {matched} of {searched} files, {count} total matches");
+    }
+
+    Ok(Summary { count, errors })
+}
+
+// Search many `files` in parallel, printing their results to `out` in order, and collecting the errors of
+// those that can't be read into `errors`. Returns the number of selected lines, with the number of files
+// searched and of those with a match.
+fn search_files(
+    config: &Config,
+    matcher: &Matcher,
+    color: Option<HighlightColor>,
+    encoding: Option<&'static Encoding>,
+    files: &[PathBuf],
+    out: &mut dyn Write,
+    errors: &mut Vec<String>,
+) -> Result<(usize, (usize, usize)), MgrepError> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.threads)
+        .build()
+        .map_err(io::Error::other)?;
+
+    // Set once any file has a match, so in quiet mode the remaining files are skipped.
+    let found = AtomicBool::new(false);
+    // With `--max-total`, set once the files printed first have enough matches, so those after them are
+    // skipped. Each file's count is recorded (`usize::MAX` until it's searched), and summed in order over
+    // the files searched so far without a gap, whose number is kept with the sum.
+    let capped = AtomicBool::new(false);
+    let counts: Vec<AtomicUsize> = files.iter().map(|_| AtomicUsize::new(usize::MAX)).collect();
+    let searched_first = Mutex::new((0, 0));
+
+    // The progress line would be mixed with the results (or end up in a log), so it needs a terminal.
+    let progress = (config.progress && io::stderr().is_terminal())
+        .then(|| progress::Progress::new(Instant::now()));
+
+    // Each file is searched in parallel into its own buffer, so the output of different
+    // files is never interleaved and is printed below in the order the files were found.
+    // With `--max-total`, the input read from each file with a match is kept too, in case the total is reached
+    // in the middle of it (see below).
+    type Searched = (Vec<u8>, io::Result<usize>, Option<Recorded>);
+    let outputs: Vec<Searched> = pool.install(|| {
+        files
+            .par_iter()
+            .enumerate()
+            .map(|(index, file)| {
+                let mut output = Vec::new();
+                if (config.quiet && found.load(Ordering::Relaxed))
+                    || capped.load(Ordering::Relaxed)
+                    || config.cancelled.load(Ordering::Relaxed)
+                {
+                    return (output, Ok(0), None);
+                }
+
+                let filename = file.display().to_string();
+                let mut recorded = None;
+                let result = open_file(config, encoding, file).and_then(|reader| {
+                    if config.max_total.is_none() {
+                        return print_matches(
+                            config,
+                            matcher,
+                            color,
+                            Some(&filename),
+                            reader,
+                            &mut output,
+                        );
+                    }
+                    let mut recording = Recording::new(reader);
+                    let count = print_matches(
+                        config,
+                        matcher,
+                        color,
+                        Some(&filename),
+                        &mut recording,
+                        &mut output,
+                    )?;
+                    recorded = (count > 0).then(|| recording.finish());
+                    Ok(count)
+                });
+
+                if let Ok(1..) = result {
+                    found.store(true, Ordering::Relaxed);
+                }
+
+                if let Some(max_total) = config.max_total {
+                    counts[index].store(*result.as_ref().unwrap_or(&0), Ordering::Relaxed);
+                    let mut searched_first = searched_first.lock().unwrap();
+                    let (next, sum) = &mut *searched_first;
+                    while let Some(count) = counts
+                        .get(*next)
+                        .map(|count| count.load(Ordering::Relaxed))
+                        .filter(|&count| count != usize::MAX)
+                    {
+                        *sum += count;
+                        *next += 1;
+                    }
+                    if *sum >= max_total {
+                        capped.store(true, Ordering::Relaxed);
+                    }
+                }
+
+                // Each report overwrites the previous one, on the same line.
+                if let Some(report) = progress.as_ref().and_then(|progress| {
+                    progress.record(*result.as_ref().unwrap_or(&0), Instant::now())
+                }) {
+                    eprint!("\r{report}");
+                }
+
+                (output, result, recorded)
+            })
+            .collect()
+    });
+
+    // The last report is erased before the results are printed.
+    if progress.is_some() {
+        eprint!("\r\x1b[K");
+    }
+
+    // Counts and filenames already name their file, so only the lines are grouped.
+    let groups_lines = !(config.count
+        || config.count_matches
+        || config.files_with_matches
+        || config.files_without_match
+        || config.json);
+    let heading = config.heading && groups_lines;
+    let separated = (config.heading || config.separate_files) && groups_lines;
+    let mut grouped = false;
+
+    let mut total = 0;
+    let mut searched = 0;
+    let mut matched = 0;
+    for (file, (mut output, mut result, recorded)) in files.iter().zip(outputs) {
+        // The file where the total is reached is searched again for its first lines only, in the input it was
+        // read from the first time (so `--pre` isn't run again, and the file isn't opened again).
+        if let Some(max_total) = config.max_total {
+            let remaining = max_total - total;
+            if remaining == 0 {
+                break;
+            }
+            if let Some(recorded) =
+                recorded.filter(|_| result.as_ref().is_ok_and(|&count| count > remaining))
+            {
+                let config = Config {
+                    max_count: Some(remaining),
+                    ..config.clone()
+                };
+                output.clear();
+                result = print_matches(
+                    &config,
+                    matcher,
+                    color,
+                    Some(&file.display().to_string()),
+                    recorded.reader(),
+                    &mut output,
+                );
+            }
+        }
+
+        // Each group is the filename on its own line, then its lines, with a blank line between groups.
+        if separated && !output.is_empty() {
+            if grouped {
+                writeln!(out)?;
+            }
+            if heading {
+                let colors = color.map(|_| config.prefix_colors.filename.as_str());
+                write_sgr(out, colors, &file.display())?;
+                writeln!(out)?;
+            }
+            grouped = true;
+        }
+        out.write_all(&output)?;
+
+        // One unreadable file (e.g. not UTF-8) shouldn't abort the whole walk.
+        match result {
+            Ok(count) => {
+                total += count;
+                searched += 1;
+                matched += usize::from(count > 0);
+            }
+            Err(err) if !config.no_messages => {
+                errors.push(format!("mgrep: {}: {err}", file.display()))
+            }
+            Err(_) => {}
+        }
+    }
+
+    Ok((total, (searched, matched)))
+}
+
+// A writer that stops writing to `inner` once the search is cancelled (see `run`).
+struct Cancellable<W> {
+    inner: W,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<W: Write> Write for Cancellable<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        check_cancelled(&self.cancelled)?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        check_cancelled(&self.cancelled)?;
+        self.inner.flush()
+    }
+}
+
+// Fails once the search is `cancelled` (see `run`), so that it stops at the next line or write.
+fn check_cancelled(cancelled: &AtomicBool) -> io::Result<()> {
+    if cancelled.load(Ordering::Relaxed) {
+        return Err(io::Error::other(
+            "the search was cancelled after its timeout",
+        ));
+    }
+    Ok(())
+}
+
+// A reader that keeps a copy of everything read through it, so the same input can be searched again.
+struct Recording<R> {
+    reader: R,
+    recorded: Recorded,
+}
+
+// The input read through a `Recording`.
+struct Recorded {
+    input: Vec<u8>,
+    // The size of the first chunk, in which binary input is detected (see `is_binary`).
+    first_chunk: Option<usize>,
+}
+
+impl<R: BufRead> Recording<R> {
+    fn new(reader: R) -> Recording<R> {
+        Recording {
+            reader,
+            recorded: Recorded {
+                input: Vec::new(),
+                first_chunk: None,
+            },
+        }
+    }
+
+    fn finish(self) -> Recorded {
+        self.recorded
+    }
+}
+
+impl<R: BufRead> Read for Recording<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = self.reader.read(buf)?;
+        self.recorded.input.extend_from_slice(&buf[..length]);
+        Ok(length)
+    }
+}
+
+impl<R: BufRead> BufRead for Recording<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let chunk = self.reader.fill_buf()?;
+        self.recorded.first_chunk.get_or_insert(chunk.len());
+        Ok(chunk)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        // Until it's consumed, the same chunk is returned again, without reading.
+        if let Ok(chunk) = self.reader.fill_buf() {
+            self.recorded.input.extend_from_slice(&chunk[..amount]);
+        }
+        self.reader.consume(amount);
+    }
+}
+
+impl Recorded {
+    // The recorded input, in chunks the size of the first one, so it's detected as binary (or not) the same way.
+    fn reader(&self) -> impl BufRead + '_ {
+        BufReader::with_capacity(self.first_chunk.unwrap_or(1).max(1), &self.input[..])
+    }
+}
+
+// Open a file to search. UTF-8 files are read line by line, so even huge ones are never loaded whole.
+// Files in another encoding (`--encoding`, or UTF-16 with a byte order mark) are read whole and transcoded
+// to UTF-8 first, with any invalid sequence replaced by U+FFFD.
+// With `--mmap`, the file is memory-mapped and searched in place instead, unless it can't be.
+// With `--pre`, the output of the preprocessor is searched instead, read whole.
+// With `--search-zip`, a `.gz` file is decompressed as it's read, and never mapped.
+fn open_file(
+    config: &Config,
+    encoding: Option<&'static Encoding>,
+    path: &Path,
+) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+
+    if let Some(pre) = &config.pre {
+        let bytes = preprocess(pre, path, file, config.timeout)?;
+        let encoding = file_encoding(encoding, &bytes);
+        if encoding == UTF_8 {
+            return Ok(Box::new(io::Cursor::new(bytes)));
+        }
+        return Ok(transcode(encoding, &bytes));
+    }
+
+    // Files that report no length (empty, or generated like those of `/proc`) are read, which works for both.
+    // Mapping fails on the files that aren't regular (e.g. pipes), which are read too.
+    let gzip = config.search_zip && path.extension().is_some_and(|extension| extension == "gz");
+
+    if config.mmap && !gzip && file.metadata()?.len() > 0 {
+        // SAFETY: The map is only read. A file truncated by another process while it's searched can still
+        // crash the search (with SIGBUS), which is the tradeoff `--mmap` opts into.
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            let encoding = file_encoding(encoding, &map);
+            if encoding == UTF_8 {
+                return Ok(Box::new(io::Cursor::new(map)));
+            }
+            return Ok(transcode(encoding, &map));
+        }
+    }
+
+    // Concatenated gzip members (e.g. appended by `gzip -c >>`) are all decompressed.
+    let file: Box<dyn Read> = if gzip {
+        Box::new(MultiGzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let mut reader = BufReader::new(file);
+    let encoding = file_encoding(encoding, reader.fill_buf()?);
+    if encoding == UTF_8 {
+        return Ok(Box::new(reader));
+    }
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    Ok(transcode(encoding, &bytes))
+}
+
+// The output of the preprocessor command `pre` run on the file at `path`, given as its argument and as its stdin
+// (already opened as `file`), like ripgrep's `--pre`.
+//
+// A command that can't be run, that fails, or that's still running after `timeout` (then killed), is an error
+// for this file only (its stderr is in the message).
+fn preprocess(
+    pre: &str,
+    path: &Path,
+    file: File,
+    timeout: Option<Duration>,
+) -> io::Result<Vec<u8>> {
+    let child = Command::new(pre)
+        .arg(path)
+        .stdin(file)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("can't run --pre command '{pre}': {err}"),
+            )
+        })?;
+    let output = match timeout {
+        Some(timeout) => wait_with_timeout(child, timeout)
+            .map_err(|err| io::Error::new(err.kind(), format!("--pre command '{pre}' {err}")))?,
+        None => child.wait_with_output()?,
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "--pre command '{pre}' failed ({}): {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+// The output of `child`, once it exits, or a `TimedOut` error after killing it if it's still running after
+// `timeout`.
+fn wait_with_timeout(mut child: Child, timeout: Duration) -> io::Result<Output> {
+    let deadline = Instant::now() + timeout;
+
+    // The pipes are read while waiting, so that a child writing more than they hold isn't blocked.
+    let read_pipe = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut bytes = Vec::new();
+            if let Some(mut pipe) = pipe {
+                pipe.read_to_end(&mut bytes)?;
+            }
+            Ok::<_, io::Error>(bytes)
+        })
+    };
+    let stdout = read_pipe(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = read_pipe(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            // Killing fails only if the child just exited, which is reaped all the same.
+            let _ = child.kill();
+            child.wait()?;
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out after {timeout:?}"),
+            ));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let join = |reader: thread::JoinHandle<io::Result<Vec<u8>>>| {
+        reader
+            .join()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    };
+
+    Ok(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    })
+}
+
+// The encoding of a file starting with `start`: the given one, else the one of its byte order mark, else UTF-8.
+fn file_encoding(encoding: Option<&'static Encoding>, start: &[u8]) -> &'static Encoding {
+    match encoding {
+        Some(encoding) => encoding,
+        None => match Encoding::for_bom(start) {
+            Some((encoding, _)) => encoding,
+            None => UTF_8,
+        },
+    }
+}
+
+// The whole `bytes` of a file, transcoded from `encoding` to UTF-8.
+fn transcode(encoding: &'static Encoding, bytes: &[u8]) -> Box<dyn BufRead> {
+    // A byte order mark wins over the given encoding, and isn't part of the text.
+    let (text, _, _) = encoding.decode(bytes);
+
+    Box::new(io::Cursor::new(text.into_owned().into_bytes()))
+}
+
+// The encoding named by `label`, like `latin1`, `utf-16le` or `shift_jis` (see the WHATWG Encoding Standard).
+fn encoding_for(label: &str) -> Result<&'static Encoding, MgrepError> {
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| MgrepError::UnknownEncoding(label.to_string()))
+}
+
+// The files under `dir` that a recursive search reads, filtered (`--include`, `--exclude`, `--max-filesize`,
+// ignore files, hidden files) and sorted (`--sort`) as configured.
+fn files_to_search(config: &Config, dir: &Path) -> Vec<PathBuf> {
+    let mut files = walk::collect_files(
+        dir,
+        &walk::Filters {
+            include: &config.include,
+            exclude: &config.exclude,
+            max_filesize: config.max_filesize,
+            max_depth: config.max_depth,
+            ignore: !config.no_ignore,
+            follow: config.follow,
+            hidden: config.hidden,
+            no_messages: config.no_messages,
+        },
+    );
+    // The whole list is known before searching, so sorting it makes the output reproducible.
+    if let Some(sort) = config.sort {
+        sort.sort(&mut files);
+    }
+
+    files
+}
+
+/// Searches the lines of `reader` (read from `filename`, if it isn't stdin) and prints to `out` every matching line
+/// as soon as it's found, prefixed by the filename when searching many files, and by the line number.
+/// In count mode only the number of matching lines is printed, as `filename:count` when searching many files.
+/// With `--count-matches` the occurrences of the query are counted instead, several per line if need be.
+/// In JSON mode each matching line is printed as a JSON object instead.
+/// With `-l` (`-L`) only the filename is printed, if the input has (doesn't have) a match.
+///
+/// Only the lines kept for the before-context are buffered, so memory doesn't grow with the input size.
+///
+/// Inputs detected as binary are searched according to `config.binary_files`: by default only
+/// `Binary file X matches` is printed, at the first selected line.
+///
+/// # Returns
+/// - `Ok(usize)`: The number of selected lines (up to the first one, if the search stopped there).
+/// - `Err(io::Error)`: If a line can't be read or written.
+fn print_matches<R: BufRead, W: Write>(
+    config: &Config,
+    matcher: &Matcher,
+    color: Option<HighlightColor>,
+    filename: Option<&str>,
+    mut reader: R,
+    out: &mut W,
+) -> io::Result<usize> {
+    // Byte offset of the next line in the input, for `-b`.
+    let mut offset = 0;
+
+    // A UTF-8 byte order mark (e.g. from Windows editors) isn't part of the first line.
+    if reader.fill_buf()?.starts_with(UTF8_BOM.as_bytes()) {
+        reader.consume(UTF8_BOM.len());
+        offset = UTF8_BOM.len();
+    }
+
+    // Only the first chunk is inspected, without consuming it.
+    let binary = config.binary_files != BinaryFiles::Text
+        && is_binary(reader.fill_buf()?, config.null_data_input);
+    if binary && config.binary_files == BinaryFiles::WithoutMatch {
+        return Ok(0);
+    }
+    // Binary (or forced text) lines may not be valid UTF-8, which is then replaced instead of failing.
+    let lossy = binary || config.binary_files == BinaryFiles::Text;
+    let (delimiter, terminator) = record_delimiter(config);
+
+    // The whole input is searched at once, so that matches can span lines.
+    if config.multiline {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let contents = to_text(&bytes, lossy)?;
+        let selection = select_across_lines(config, matcher, &contents, delimiter);
+        let count = selection.count();
+
+        let prints_lines = !(config.quiet
+            || config.files_with_matches
+            || config.files_without_match
+            || config.count
+            || config.count_matches);
+        if prints_lines && count > 0 {
+            if binary {
+                let name = filename.unwrap_or(STDIN_NAME);
+                writeln!(out, "Binary file {name} matches")?;
+            } else {
+                print_selection(out, config, color, filename, &contents, &selection, offset)?;
+            }
+        }
+        let occurrences = selection.spans.len();
+        print_summary(
+            out,
+            config,
+            color,
+            filename,
+            (count, occurrences),
+            selection.lines.len(),
+        )?;
+
+        return Ok(count);
+    }
+
+    let with_context = config.before > 0 || config.after > 0;
+
+    let mut count = 0;
+    // Every occurrence of the query in the selected lines, for `--count-matches`.
+    let mut occurrences = 0;
+    // The latest unprinted lines, which may become the before-context of the next match.
+    let mut before_lines: VecDeque<(usize, usize, String)> = VecDeque::with_capacity(config.before);
+    // Number of the last line printed (0 for none) and of the last line of trailing context owed.
+    let mut last_printed = 0;
+    let mut after_until = 0;
+    // The last matching line printed, with `--dedupe`, and its number.
+    let mut last_match: Option<(usize, String)> = None;
+
+    let mut bytes = Vec::new();
+    let mut number = 0;
+
+    loop {
+        // Everything found so far is printed before waiting for the next line, which may take a while.
+        if config.line_buffered {
+            out.flush()?;
+        }
+        check_cancelled(&config.cancelled)?;
+
+        let line = read_line(&mut reader, &mut bytes, lossy, delimiter).transpose();
+        let Some(line) = line else {
+            break;
+        };
+        number += 1;
+        let line_offset = offset;
+
+        // Past the limit, lines are only read for the trailing context of the last match.
+        let limit_reached = config.max_count.is_some_and(|max_count| count >= max_count);
+        if limit_reached && number > after_until {
+            // The line just read isn't searched, nor counted by `--ratio`.
+            number -= 1;
+            break;
+        }
+
+        let (line, length) = line?;
+        offset += length;
+
+        if limit_reached {
+            print_context(out, config, color, filename, number, line_offset, &line)?;
+            continue;
+        }
+
+        // Searches for the ´query´
+        if !matcher.selects(&line, config.invert) {
+            if config.passthru {
+                // Printed like context, so only the selected lines are highlighted.
+                print_context(out, config, color, filename, number, line_offset, &line)?;
+                last_printed = number;
+            } else if number <= after_until {
+                // Trailing context of the previous match.
+                print_context(out, config, color, filename, number, line_offset, &line)?;
+                last_printed = number;
+            } else if config.before > 0 {
+                if before_lines.len() == config.before {
+                    before_lines.pop_front();
+                }
+                before_lines.push_back((number, line_offset, line));
+            }
+            continue;
+        }
+
+        count += 1;
+
+        // The first match is enough to know whether the filename is listed (or the exit status), so stop reading.
+        if config.files_with_matches || config.files_without_match || config.quiet {
+            break;
+        }
+
+        // Print just how many lines (or occurrences) were selected, once the input is exhausted.
+        // Inverted results contain no occurrence to count.
+        if config.count || config.count_matches {
+            if config.counts_matches() && !config.invert {
+                occurrences += matcher.find_matches(&line).len();
+            }
+            continue;
+        }
+
+        // One object per matching line, without highlighting or context.
+        if config.json {
+            let spans = if config.invert {
+                Vec::new()
+            } else {
+                matcher.find_matches(&line)
+            };
+            writeln!(out, "{}", json_line(filename, number, &line, &spans))?;
+            continue;
+        }
+
+        // The lines of a binary file aren't printed, only that it has a match.
+        if binary {
+            writeln!(
+                out,
+                "Binary file {} matches",
+                filename.unwrap_or(STDIN_NAME)
+            )?;
+            break;
+        }
+
+        // Each match on its own line with its position and the whole line, for Vim's quickfix list (`:cexpr`).
+        if config.vimgrep {
+            let name = filename.unwrap_or(STDIN_NAME);
+            for (start, _) in matcher.find_matches(&line) {
+                writeln!(out, "{name}:{number}:{}:{line}", column_of(&line, start))?;
+            }
+            continue;
+        }
+
+        // Each match on its own line, without context. Inverted results contain no match to print.
+        if config.only_matching {
+            if !config.invert {
+                for (start, end) in matcher.find_matches(&line) {
+                    let column = column_of(&line, start);
+                    let position = Position {
+                        number,
+                        offset: line_offset + start,
+                        column: Some(column),
+                    };
+                    print_prefix(out, config, color, filename, position, ':')?;
+                    let text = config.replace.as_deref().unwrap_or(&line[start..end]);
+                    print_colored(out, color, text)?;
+                    write!(out, "{terminator}")?;
+                }
+            }
+            continue;
+        }
+
+        // A line repeating the matching line right before it is left out (like `uniq`), but still counted.
+        if config.dedupe
+            && last_match
+                .as_ref()
+                .is_some_and(|(previous, text)| *previous + 1 == number && *text == line)
+        {
+            last_match = Some((number, line));
+            last_printed = number;
+            after_until = number + config.after;
+            continue;
+        }
+
+        // Windows that don't touch the previous one form a new group.
+        let first = before_lines.front().map_or(number, |(first, _, _)| *first);
+        if with_context && last_printed > 0 && first > last_printed + 1 {
+            if let Some(separator) = &config.group_separator {
+                writeln!(out, "{separator}")?;
+            }
+        }
+        for (number, offset, line) in before_lines.drain(..) {
+            print_context(out, config, color, filename, number, offset, &line)?;
+        }
+
+        // Inverted results contain no match, so their column is the start of the line.
+        let column = if config.column && !config.invert {
+            matcher
+                .find_matches(&line)
+                .first()
+                .map_or(1, |&(start, _)| column_of(&line, start))
+        } else {
+            1
+        };
+
+        // Print the query
+        let position = Position {
+            number,
+            offset: line_offset,
+            column: Some(column),
+        };
+        print_prefix(out, config, color, filename, position, ':')?;
+        // Inverted results contain no match, so there's nothing to highlight.
+        let spans = if config.invert || (color.is_none() && config.replace.is_none()) {
+            Vec::new()
+        } else {
+            matcher.find_matches(&line)
+        };
+        // The matches were found in the whole line, but only its trimmed part is printed with `--trim`.
+        let (text, spans) = if config.trim {
+            trim_line(&line, spans)
+        } else {
+            (line.as_str(), spans)
+        };
+        let (text, spans, omitted) = truncate_line(text, spans, config.max_columns);
+        match &config.replace {
+            Some(replacement) if !config.invert => {
+                write_replaced(out, color, text, &spans, replacement)?
+            }
+            _ => write_highlighted(out, color, text, &spans)?,
+        }
+        write_omitted(out, omitted)?;
+        write!(out, "{terminator}")?;
+
+        last_printed = number;
+        after_until = number + config.after;
+        if config.dedupe {
+            last_match = Some((number, line));
+        }
+    }
+
+    print_summary(out, config, color, filename, (count, occurrences), number)?;
+
+    Ok(count)
+}
+
+// Print what's left once an input was searched: its filename with `-l` (`-L`), or its number of selected
+// lines (`count`) or of their `occurrences` with `-c` and `--count-matches`, out of the `lines` searched.
+fn print_summary<W: Write>(
+    out: &mut W,
+    config: &Config,
+    color: Option<HighlightColor>,
+    filename: Option<&str>,
+    (count, occurrences): (usize, usize),
+    lines: usize,
+) -> io::Result<()> {
+    if config.quiet {
+        // Nothing is printed, the caller only reports whether a line was selected.
+    } else if config.files_with_matches || config.files_without_match {
+        if (count > 0) == config.files_with_matches {
+            let terminator = if config.null_data { '\0' } else { '\n' };
+            let filename = filename.unwrap_or(STDIN_NAME);
+            write_sgr(
+                out,
+                color.map(|_| config.prefix_colors.filename.as_str()),
+                &filename,
+            )?;
+            write!(out, "{terminator}")?;
+        }
+    } else if sums_counts(config) {
+        // Only the sum over all the files is printed, by `run`.
+    } else if config.count || config.count_matches {
+        let counted = if config.counts_matches() {
+            occurrences
+        } else {
+            count
+        };
+        // A file without a match is left out of the counts of several files, unless `--include-zero`.
+        if counted == 0 && !config.include_zero && filename.is_some() && config.shows_filename() {
+            return Ok(());
+        }
+        let total = if config.counts_matches() {
+            occurrences.to_string()
+        } else if config.ratio {
+            ratio(count, lines)
+        } else {
+            count.to_string()
+        };
+        if let Some(filename) = filename.filter(|_| config.shows_filename()) {
+            print_filename(out, config, color, filename, ':')?;
+        }
+        writeln!(out, "{total}")?;
+    }
+
+    Ok(())
+}
+
+// The lines of an input searched with `--multiline`, and the matches found across them.
+struct Selection {
+    // The byte span of each line in the input, without its delimiter (nor the `\r` of a `\r\n`).
+    lines: Vec<(usize, usize)>,
+    // Whether each line is part of a match.
+    selected: Vec<bool>,
+    // The byte span of each match in the input, up to `--max-count` selected lines.
+    spans: Vec<(usize, usize)>,
+}
+
+impl Selection {
+    // The number of selected lines.
+    fn count(&self) -> usize {
+        self.selected.iter().filter(|&&selected| selected).count()
+    }
+
+    // The index of the line holding the byte offset `byte` (its delimiter included).
+    fn line_of(&self, byte: usize) -> usize {
+        self.lines.partition_point(|&(start, _)| start <= byte) - 1
+    }
+}
+
+// Search the whole `contents` at once with `--multiline`, so that a match can span several lines, which are all
+// selected. Lines end at `delimiter`, like when they're read one by one (see `read_line`).
+fn select_across_lines(
+    config: &Config,
+    matcher: &Matcher,
+    contents: &str,
+    delimiter: u8,
+) -> Selection {
+    let bytes = contents.as_bytes();
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let end =
+            memchr::memchr(delimiter, &bytes[start..]).map_or(bytes.len(), |index| start + index);
+        let crlf = delimiter == b'\n' && end < bytes.len() && bytes[start..end].ends_with(b"\r");
+        lines.push((start, if crlf { end - 1 } else { end }));
+        start = end + 1;
+    }
+
+    let mut selection = Selection {
+        selected: vec![false; lines.len()],
+        lines,
+        spans: Vec::new(),
+    };
+    let mut count = 0;
+    // A match at the very end (e.g. `^` after the last line break) isn't on any line.
+    for (start, end) in matcher.find_matches(contents) {
+        if start >= contents.len() || config.max_count.is_some_and(|max_count| count >= max_count) {
+            break;
+        }
+        let first = selection.line_of(start);
+        let last = if end > start {
+            selection.line_of(end - 1)
+        } else {
+            first
+        };
+        for selected in &mut selection.selected[first..=last] {
+            count += usize::from(!*selected);
+            *selected = true;
+        }
+        selection.spans.push((start, end));
+    }
+
+    selection
+}
+
+// Print the lines selected with `--multiline` (see `select_across_lines`) with their matched parts highlighted,
+// and the context lines around them. With `-o`, each whole match is printed instead, after the position of its
+// start. `offset` is the byte offset of `contents` in the input, for `-b`.
+fn print_selection<W: Write>(
+    out: &mut W,
+    config: &Config,
+    color: Option<HighlightColor>,
+    filename: Option<&str>,
+    contents: &str,
+    selection: &Selection,
+    offset: usize,
+) -> io::Result<()> {
+    let terminator = record_delimiter(config).1;
+
+    if config.only_matching {
+        for &(start, end) in &selection.spans {
+            let line = selection.line_of(start);
+            let line_start = selection.lines[line].0;
+            let position = Position {
+                number: line + 1,
+                offset: offset + start,
+                column: Some(column_of(&contents[line_start..], start - line_start)),
+            };
+            print_prefix(out, config, color, filename, position, ':')?;
+            print_colored(out, color, &contents[start..end])?;
+            write!(out, "{terminator}")?;
+        }
+        return Ok(());
+    }
+
+    // A line is printed when it's selected, or close enough before or after a selected line.
+    let last = selection.lines.len() - 1;
+    let shown = |line: usize| {
+        selection.selected[line.saturating_sub(config.after)..=(line + config.before).min(last)]
+            .contains(&true)
+    };
+    let with_context = config.before > 0 || config.after > 0;
+    let mut last_printed = None;
+    // The first match that doesn't end before the current line.
+    let mut next_span = 0;
+
+    for (line, &(start, end)) in selection.lines.iter().enumerate() {
+        if !shown(line) {
+            continue;
+        }
+        // Windows that don't touch the previous one form a new group.
+        if with_context && last_printed.is_some_and(|last_printed| line > last_printed + 1) {
+            if let Some(separator) = &config.group_separator {
+                writeln!(out, "{separator}")?;
+            }
+        }
+        last_printed = Some(line);
+
+        let text = &contents[start..end];
+        if !selection.selected[line] {
+            print_context(out, config, color, filename, line + 1, offset + start, text)?;
+            continue;
+        }
+
+        // The parts of the matches on this line, moved to it.
+        while selection.spans[next_span].1 <= start && next_span + 1 < selection.spans.len() {
+            next_span += 1;
+        }
+        let spans: Vec<(usize, usize)> = selection.spans[next_span..]
+            .iter()
+            .take_while(|&&(match_start, _)| match_start < end)
+            .map(|&(match_start, match_end)| {
+                (match_start.max(start) - start, match_end.min(end) - start)
+            })
+            .filter(|(match_start, match_end)| match_start < match_end)
+            .collect();
+        // A line continuing a match from the line before starts with it.
+        let position = Position {
+            number: line + 1,
+            offset: offset + start,
+            column: Some(
+                spans
+                    .first()
+                    .map_or(1, |&(match_start, _)| column_of(text, match_start)),
+            ),
+        };
+        print_prefix(out, config, color, filename, position, ':')?;
+        let (text, spans) = if config.trim {
+            trim_line(text, spans)
+        } else {
+            (text, spans)
+        };
+        let (text, spans, omitted) = truncate_line(text, spans, config.max_columns);
+        write_highlighted(out, color, text, &spans)?;
+        write_omitted(out, omitted)?;
+        write!(out, "{terminator}")?;
+    }
+
+    Ok(())
+}
+
+// The `count` of selected lines out of the `lines` searched, with `--ratio`, e.g. `42/1000 (4.2%)`.
+fn ratio(count: usize, lines: usize) -> String {
+    let percentage = if lines == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / lines as f64
+    };
+
+    format!("{count}/{lines} ({percentage:.1}%)")
+}
+
+// Whether `--total` replaces the count of each file by their sum, which only applies to `-c` (not to
+// `--count-matches`, nor to the modes that print no count at all).
+fn sums_counts(config: &Config) -> bool {
+    config.total
+        && config.count
+        && !(config.counts_matches()
+            || config.files
+            || config.quiet
+            || config.files_with_matches
+            || config.files_without_match)
+}
+
+// The byte separating the input records and the character terminating the printed ones:
+// NUL with `-z`, a newline otherwise.
+fn record_delimiter(config: &Config) -> (u8, char) {
+    if config.null_data_input {
+        (b'\0', '\0')
+    } else {
+        (b'\n', '\n')
+    }
+}
+
+// Read the next record (a line, unless `delimiter` is NUL) into `bytes` (reused between calls) without its
+// delimiter, with the number of bytes it took in the input, or `None` at the end.
+// Invalid UTF-8 is an error, unless `lossy` is set and it's replaced instead.
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    bytes: &mut Vec<u8>,
+    lossy: bool,
+    delimiter: u8,
+) -> io::Result<Option<(String, usize)>> {
+    bytes.clear();
+    let length = reader.read_until(delimiter, bytes)?;
+    if length == 0 {
+        return Ok(None);
+    }
+
+    // Same line endings as `BufRead::lines`: `\n` or `\r\n`.
+    if bytes.ends_with(&[delimiter]) {
+        bytes.pop();
+        if delimiter == b'\n' && bytes.ends_with(b"\r") {
+            bytes.pop();
+        }
+    }
+
+    Ok(Some((to_text(bytes, lossy)?, length)))
+}
+
+// The text of `bytes`. Invalid UTF-8 is an error, unless `lossy` is set and it's replaced instead.
+fn to_text(bytes: &[u8], lossy: bool) -> io::Result<String> {
+    if lossy {
+        return Ok(String::from_utf8_lossy(bytes).into_owned());
+    }
+
+    std::str::from_utf8(bytes).map(str::to_string).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        )
+    })
+}
+
+/// Detects binary content from the first chunk of an input.
+///
+/// # Arguments
+/// - `chunk`: The beginning of the input.
+/// - `nul_separated`: Whether NUL bytes separate the records (`-z`), so they don't make the input binary.
+///
+/// # Returns
+/// - `true` if `chunk` contains a NUL byte (unless `nul_separated`) or isn't valid UTF-8.
+/// - `false` otherwise (a multibyte character cut at the end of the chunk is still valid).
+fn is_binary(chunk: &[u8], nul_separated: bool) -> bool {
+    (!nul_separated && chunk.contains(&0))
+        || std::str::from_utf8(chunk).is_err_and(|err| err.error_len().is_some())
+}
+
+// Where a printed line (or match, with `-o`) is in its input, for its prefix.
+struct Position {
+    number: usize,
+    offset: usize,
+    // Context lines have no column to print.
+    column: Option<usize>,
+}
+
+// Print the `filename:`, `number:`, `offset:` and `column:` prefixes of a line, when enabled.
+// Like grep, the `separator` is `:` for matching lines and `-` for context lines, e.g. `poem.txt-3-...`.
+// When `color` is set, each part is colored with `config.prefix_colors`.
+fn print_prefix<W: Write>(
+    out: &mut W,
+    config: &Config,
+    color: Option<HighlightColor>,
+    filename: Option<&str>,
+    position: Position,
+    separator: char,
+) -> io::Result<()> {
+    let colors = color.map(|_| &config.prefix_colors);
+    // With `--heading` the filename was already printed above the lines (see `run`).
+    if let Some(filename) = filename.filter(|_| config.shows_filename() && !config.heading) {
+        print_filename(out, config, color, filename, separator)?;
+    }
+    if config.line_number {
+        write_sgr(
+            out,
+            colors.map(|colors| colors.line_number.as_str()),
+            &position.number,
+        )?;
+        write_sgr(
+            out,
+            colors.map(|colors| colors.separator.as_str()),
+            &separator,
+        )?;
+    }
+    if config.byte_offset {
+        write_sgr(
+            out,
+            colors.map(|colors| colors.byte_offset.as_str()),
+            &position.offset,
+        )?;
+        write_sgr(
+            out,
+            colors.map(|colors| colors.separator.as_str()),
+            &separator,
+        )?;
+    }
+    if let Some(column) = position.column.filter(|_| config.column) {
+        write_sgr(
+            out,
+            colors.map(|colors| colors.line_number.as_str()),
+            &column,
+        )?;
+        write_sgr(
+            out,
+            colors.map(|colors| colors.separator.as_str()),
+            &separator,
+        )?;
+    }
+    Ok(())
+}
+
+// Print a `filename` and its separator (see `filename_separator`), colored like in `print_prefix`.
+fn print_filename<W: Write>(
+    out: &mut W,
+    config: &Config,
+    color: Option<HighlightColor>,
+    filename: &str,
+    separator: char,
+) -> io::Result<()> {
+    let colors = color.map(|_| &config.prefix_colors);
+    write_sgr(
+        out,
+        colors.map(|colors| colors.filename.as_str()),
+        &filename,
+    )?;
+    match filename_separator(config, separator) {
+        // A NUL (`-Z`) isn't displayed, so it's left uncolored.
+        '\0' => write!(out, "\0"),
+        separator => write_sgr(
+            out,
+            colors.map(|colors| colors.separator.as_str()),
+            &separator,
+        ),
+    }
+}
+
+// Print `text` colored with the SGR parameters `sgr`, unless they're `None` or empty.
+fn write_sgr<W: Write + ?Sized>(
+    out: &mut W,
+    sgr: Option<&str>,
+    text: &dyn Display,
+) -> io::Result<()> {
+    match sgr.filter(|sgr| !sgr.is_empty()) {
+        Some(sgr) => write!(out, "\x1b[{sgr}m{text}\x1b[0m"),
+        None => write!(out, "{text}"),
+    }
+}
+
+// The 1-based column of the byte offset `start` in `line`, in characters (see `char_offset`).
+fn column_of(line: &str, start: usize) -> usize {
+    char_offset(line, start) + 1
+}
+
+// The 0-based character offset of the byte offset `byte` in `line`. Matches are found by byte, but the
+// positions reported to users count characters, so that editors place the cursor right on multibyte lines.
+fn char_offset(line: &str, byte: usize) -> usize {
+    line[..byte].chars().count()
+}
+
+// What follows a filename in front of a line or count: NUL with `-Z`, so any filename can be parsed back,
+// and `separator` otherwise.
+fn filename_separator(config: &Config, separator: char) -> char {
+    if config.null_data {
+        '\0'
+    } else {
+        separator
+    }
+}
+
+// Print a context line, without highlighting (only its prefix is colored, with `color`).
+fn print_context<W: Write>(
+    out: &mut W,
+    config: &Config,
+    color: Option<HighlightColor>,
+    filename: Option<&str>,
+    number: usize,
+    offset: usize,
+    line: &str,
+) -> io::Result<()> {
+    let position = Position {
+        number,
+        offset,
+        column: None,
+    };
+    print_prefix(out, config, color, filename, position, '-')?;
+    let line = if config.trim { line.trim() } else { line };
+    let (line, _, omitted) = truncate_line(line, Vec::new(), config.max_columns);
+    write!(out, "{line}")?;
+    write_omitted(out, omitted)?;
+    write!(out, "{}", record_delimiter(config).1)
+}
+
+/// Serializes a matching line as a single-line JSON object.
+///
+/// # Returns
+/// A string like `{"file":"poem.txt","line_number":4,"line":"...","matches":[{"start":5,"end":9}]}`,
+/// where `file` is `null` for stdin and each match is a span of `line` in characters (see `char_offset`),
+/// converted from the byte `spans`.
+fn json_line(
+    filename: Option<&str>,
+    number: usize,
+    line: &str,
+    spans: &[(usize, usize)],
+) -> String {
+    let file = match filename {
+        Some(filename) => format!("\"{}\"", json_escape(filename)),
+        None => "null".to_string(),
+    };
+
+    let matches: Vec<String> = spans
+        .iter()
+        .map(|&(start, end)| {
+            format!(
+                "{{\"start\":{},\"end\":{}}}",
+                char_offset(line, start),
+                char_offset(line, end)
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"file\":{file},\"line_number\":{number},\"line\":\"{}\",\"matches\":[{}]}}",
+        json_escape(line),
+        matches.join(",")
+    )
+}
+
+/// Escapes `text` to be embedded in a JSON string: quotes, backslashes and control characters.
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Compiles the query into a regular expression.
+///
+/// # Parameters
+/// - `multiline`: For a search across lines (`-U`), whether `.` also matches line breaks (`--multiline-dotall`).
+///   `^` and `$` then still match at the start and end of each line. `None` for a line-by-line search.
+///
+/// # Returns
+/// - `Ok(Regex)` if the query is a valid pattern.
+/// - `Err(MgrepError::InvalidRegex)` if the pattern fails to compile.
+fn build_regex(
+    query: &str,
+    ignore_case: bool,
+    multiline: Option<bool>,
+) -> Result<Regex, MgrepError> {
+    RegexBuilder::new(query)
+        .case_insensitive(ignore_case)
+        .multi_line(multiline.is_some())
+        .dot_matches_new_line(multiline.unwrap_or(false))
+        .build()
+        .map_err(|err| MgrepError::InvalidRegex(query.to_string(), err))
+}
+
+// Print the matches (as byte `spans` of `line`) in highlighted style, or plainly when `color` is `None`, without
+// terminating the line (see `record_delimiter`). The spans of `Matcher::find_matches` don't overlap: `aa` in
+// `aaaaa` highlights the first two pairs and leaves the last `a` plain.
+fn write_highlighted<W: Write>(
+    out: &mut W,
+    color: Option<HighlightColor>,
+    line: &str,
+    spans: &[(usize, usize)],
+) -> io::Result<()> {
+    if color.is_none() {
+        return write!(out, "{line}");
+    }
+
+    let mut start = 0;
+
+    for &(match_start, match_end) in spans {
+        // Prints before the word.
+        write!(out, "{}", &line[start..match_start])?;
+
+        // Prints the highlighted word.
+        print_colored(out, color, &line[match_start..match_end])?;
+
+        // Updates the starting position to after the word.
+        start = match_end;
+    }
+
+    // Prints the remaining line.
+    write!(out, "{}", &line[start..])
+}
+
+// The part of `line` printed with `--trim`, without its leading and trailing whitespace, and the byte `spans`
+// of its matches moved to it. Matches are cut to the trimmed part, and dropped if they were only whitespace.
+fn trim_line(line: &str, spans: Vec<(usize, usize)>) -> (&str, Vec<(usize, usize)>) {
+    let trimmed = line.trim();
+    let start = line.len() - line.trim_start().len();
+    let end = start + trimmed.len();
+
+    let spans = spans
+        .into_iter()
+        .filter_map(|(match_start, match_end)| {
+            let clipped = (match_start.clamp(start, end), match_end.clamp(start, end));
+            // Empty matches (e.g. `^` replaced with `-R`) are kept, unless they're in the whitespace.
+            let kept = if match_start == match_end {
+                (start..=end).contains(&match_start)
+            } else {
+                clipped.0 < clipped.1
+            };
+            kept.then_some((clipped.0 - start, clipped.1 - start))
+        })
+        .collect();
+
+    (trimmed, spans)
+}
+
+// The part of `line` printed with `--max-columns`: its first `max_columns` characters, with the byte `spans`
+// of its matches cut to them, and the number of characters left out. Matches past the cut aren't printed, so a
+// line matching only there shows its start without any highlight (it's still selected and counted).
+fn truncate_line(
+    line: &str,
+    spans: Vec<(usize, usize)>,
+    max_columns: Option<usize>,
+) -> (&str, Vec<(usize, usize)>, usize) {
+    let Some((cut, _)) = max_columns.and_then(|max_columns| line.char_indices().nth(max_columns))
+    else {
+        return (line, spans, 0);
+    };
+
+    let spans = spans
+        .into_iter()
+        .filter(|&(start, _)| start < cut)
+        .map(|(start, end)| (start, end.min(cut)))
+        .collect();
+
+    (&line[..cut], spans, line[cut..].chars().count())
+}
+
+// Print the marker of the characters left out of a line by `--max-columns`, if any.
+fn write_omitted<W: Write>(out: &mut W, omitted: usize) -> io::Result<()> {
+    if omitted > 0 {
+        write!(out, " [... omitted {omitted} chars]")?;
+    }
+    Ok(())
+}
+
+// Print `line` with each of its `spans` replaced by `replacement`, inserted verbatim (`$1` isn't expanded),
+// and highlighted in `color` unless it's `None`.
+fn write_replaced<W: Write>(
+    out: &mut W,
+    color: Option<HighlightColor>,
+    line: &str,
+    spans: &[(usize, usize)],
+    replacement: &str,
+) -> io::Result<()> {
+    let mut start = 0;
+
+    for &(match_start, match_end) in spans {
+        write!(out, "{}", &line[start..match_start])?;
+        print_colored(out, color, replacement)?;
+        start = match_end;
+    }
+
+    write!(out, "{}", &line[start..])
+}
+
+// Print a matched text, highlighted in `color` unless it's `None`.
+fn print_colored<W: Write>(
+    out: &mut W,
+    color: Option<HighlightColor>,
+    text: &str,
+) -> io::Result<()> {
+    match color {
+        Some(color) => write!(out, "{}{text}\x1b[0m", color.sgr()),
+        None => write!(out, "{text}"),
+    }
+}
+
+/// Finds the first occurrence of `query` in `line`, starting the search at byte `from`.
+///
+/// # Parameters
+/// - `query`: The text to find. When `ignore_case` is set it must already be lowercase.
+/// - `ignore_case`: Whether each character of `line` is compared by its lowercase form.
+///
+/// # Returns
+/// The byte span of the occurrence in `line`, or `None` if there's none (or `query` is empty).
+/// In case-insensitive mode the span covers the original characters of `line`, whose byte
+/// length may differ from `query` (e.g. `ẞ` vs `ß`).
+fn find_literal(line: &str, query: &str, ignore_case: bool, from: usize) -> Option<(usize, usize)> {
+    if query.is_empty() {
+        return None;
+    }
+
+    if !ignore_case {
+        return line[from..]
+            .find(query)
+            .map(|position| (from + position, from + position + query.len()));
+    }
+
+    // ASCII only folds to ASCII, so the bytes can be compared directly and the span has the query's length.
+    if line.is_ascii() && query.is_ascii() {
+        return find_ascii_ignore_case(&line.as_bytes()[from..], query.as_bytes())
+            .map(|position| (from + position, from + position + query.len()));
+    }
+
+    line[from..].char_indices().find_map(|(offset, _)| {
+        let match_start = from + offset;
+        lowercase_prefix_len(&line[match_start..], query)
+            .map(|len| (match_start, match_start + len))
+    })
+}
+
+/// Finds the first occurrence of the lowercase `needle` in `haystack`, ignoring ASCII case, without
+/// lowercasing (or copying) `haystack`.
+///
+/// # Returns
+/// The byte offset of the occurrence in `haystack`, or `None` if there's none.
+fn find_ascii_ignore_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    let (&first, rest) = needle.split_first()?;
+    let last_start = haystack.len().checked_sub(needle.len())?;
+
+    // Only the positions holding the first byte, in either case, are compared to the whole needle,
+    // and `memchr` jumps from one to the next much faster than a byte-by-byte loop.
+    memchr::memchr2_iter(first, first.to_ascii_uppercase(), &haystack[..=last_start])
+        .find(|&start| haystack[start + 1..start + needle.len()].eq_ignore_ascii_case(rest))
+}
+
+/// Returns the byte length of the prefix of `text` whose lowercase form is exactly `query`,
+/// or `None` if `text` doesn't start with it on a char boundary.
+fn lowercase_prefix_len(text: &str, query: &str) -> Option<usize> {
+    let mut expected = query.chars();
+
+    for (offset, c) in text.char_indices() {
+        for lower in c.to_lowercase() {
+            if expected.next() != Some(lower) {
+                return None;
+            }
+        }
+
+        if expected.as_str().is_empty() {
+            return Some(offset + c.len_utf8());
+        }
+    }
+
+    None
+}
+
+/// Checks that the span `start..end` of `line` is a standalone word, i.e. it isn't preceded or followed
+/// by a word character (see `WordChars`).
+fn is_word_bounded(line: &str, start: usize, end: usize, chars: WordChars) -> bool {
+    let is_word_char = |c: char| chars.contains(c);
+
+    let before = line[..start].chars().next_back().is_some_and(is_word_char);
+    let after = line[end..].chars().next().is_some_and(is_word_char);
+
+    !before && !after
+}
+
+/// Checks whether `line` contains `query`, as a standalone word when `word` is set.
+fn contains_query(line: &str, query: &str, word: Option<WordChars>) -> bool {
+    if let Some(chars) = word {
+        line.match_indices(query)
+            .any(|(position, found)| is_word_bounded(line, position, position + found.len(), chars))
+    } else {
+        line.contains(query)
+    }
+}
+
+/// Searches the given content for lines that contain the specified query.
+///
+/// # Parameters
+/// - `matcher`: The prepared query (see `Matcher::new`), which can be reused for many searches.
+/// - `invert`: A boolean indicating whether to select the lines that do NOT match instead.
+/// - `contents`: The text within which to search for the query.
+///
+/// # Returns
+/// Each line from `contents` that matches the query (or doesn't, when `invert` is set), paired with its
+/// 1-based line number in `contents`.
+pub fn search<'a>(matcher: &Matcher, invert: bool, contents: &'a str) -> Vec<(usize, &'a str)> {
+    // Process each line of the contents, filtering based on the presence of the query
+    // as determined by the matcher (or its absence, when inverted).
+    // Collect matching lines into a vector.
+    numbered_lines(contents)
+        .filter(|(_, line)| matcher.selects(line, invert))
+        .collect()
+}
+
+/// Lazily searches `contents` for the lines that contain the query, so nothing is collected upfront and the
+/// caller can stop at any point (e.g. with `take` or `find`).
+///
+/// # Returns
+/// Each matching line as a `Match`, from the first to the last. The spans of its matches are only found if
+/// asked for (see `Match::spans`).
+///
+/// # Examples
+/// ```
+/// use mgrep::Matcher;
+///
+/// let contents = "error: disk full\nok\nerror: timeout\nerror: retry\nerror: gave up";
+/// let matcher = Matcher::new("error", false, false, false).unwrap();
+///
+/// let first: Vec<(usize, &str)> = mgrep::matches_iter(&matcher, contents)
+///     .take(3)
+///     .map(|found| (found.line_number(), found.line()))
+///     .collect();
+/// assert_eq!(
+///     vec![(1, "error: disk full"), (3, "error: timeout"), (4, "error: retry")],
+///     first
+/// );
+///
+/// let found = mgrep::matches_iter(&matcher, contents).next().unwrap();
+/// assert_eq!(vec![(0, 5)], found.spans());
+/// ```
+pub fn matches_iter<'a>(
+    matcher: &'a Matcher,
+    contents: &'a str,
+) -> impl Iterator<Item = Match<'a>> {
+    numbered_lines(contents)
+        .filter(|(_, line)| matcher.selects(line, false))
+        .map(move |(line_number, line)| Match {
+            line_number,
+            line,
+            matcher,
+        })
+}
+
+/// A line found by `matches_iter`.
+#[derive(Clone, Copy)]
+pub struct Match<'a> {
+    line_number: usize,
+    line: &'a str,
+    // Finds the spans of the matches, only when they're asked for.
+    matcher: &'a Matcher,
+}
+
+impl<'a> Match<'a> {
+    /// The 1-based number of the line in the searched text.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// The line, without its line ending.
+    pub fn line(&self) -> &'a str {
+        self.line
+    }
+
+    /// The byte span of each match in the line, from left to right and without overlapping, e.g. to highlight them.
+    pub fn spans(&self) -> Vec<(usize, usize)> {
+        self.matcher.find_matches(self.line)
+    }
+}
+
+// The lines of `contents`, numbered from 1. Numbering happens before any filtering, so it reflects the original
+// position of each line. A leading byte order mark and a `\r` left at the end of a line (CRLF endings) aren't part
+// of the text.
+fn numbered_lines(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    contents
+        .strip_prefix(UTF8_BOM)
+        .unwrap_or(contents)
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.strip_suffix('\r').unwrap_or(line)))
+}
+
+/// Searches `contents` for the lines containing `query`, returning them as owned strings.
+///
+/// This is the simplest entry point for embedding mgrep: a literal, line-by-line search with no CLI
+/// involvement and results that don't borrow from `contents`. Use `search` for the zero-copy version
+/// with more options.
+///
+/// # Examples
+/// ```
+/// let contents = "Rust:\nsafe, fast, productive.\nPick three.\nTrust me.";
+///
+/// assert_eq!(vec!["Rust:", "Trust me."], mgrep::search_lines("rUsT", true, contents));
+/// assert_eq!(vec!["Pick three."], mgrep::search_lines("three", false, contents));
+/// ```
+pub fn search_lines(query: &str, ignore_case: bool, contents: &str) -> Vec<String> {
+    // Only a regex can fail to build, so a literal search always has results.
+    Matcher::new(query, ignore_case, false, false)
+        .map(|matcher| {
+            search(&matcher, false, contents)
+                .into_iter()
+                .map(|(_, line)| line.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Searches `contents` for the lines containing `query`, as bytes, so neither has to be valid UTF-8 (e.g. a
+/// log with stray Latin-1 bytes, or a binary file).
+///
+/// Lines are split on `\n` (a trailing `\r` is dropped), and the query is matched as a byte substring. With
+/// `ignore_case`, only ASCII letters are folded; any other byte must match exactly. Decode the lines lossily
+/// (e.g. with `String::from_utf8_lossy`) to print them.
+///
+/// # Examples
+/// ```
+/// let contents = b"caf\xe9 ouvert\nclosed\nCAF\xe9 ferm\xe9";
+///
+/// assert_eq!(
+///     vec![&b"caf\xe9 ouvert"[..], b"CAF\xe9 ferm\xe9"],
+///     mgrep::search_bytes(b"caf\xe9", true, contents)
+/// );
+/// ```
+pub fn search_bytes<'a>(query: &[u8], ignore_case: bool, contents: &'a [u8]) -> Vec<&'a [u8]> {
+    // Like `str::lines`, a final newline doesn't start an empty last line.
+    let contents = contents.strip_suffix(b"\n").unwrap_or(contents);
+    if contents.is_empty() {
+        return Vec::new();
+    }
+
+    let query = if ignore_case {
+        query.to_ascii_lowercase()
+    } else {
+        query.to_vec()
+    };
+    let finder = memchr::memmem::Finder::new(&query);
+
+    contents
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| {
+            if ignore_case {
+                query.is_empty() || find_ascii_ignore_case(line, &query).is_some()
+            } else {
+                finder.find(line).is_some()
+            }
+        })
+        .collect()
+}
+
+/// Streaming counterpart of `search`: reads `reader` line by line and yields each matching line as it's found,
+/// so the whole input is never held in memory.
+///
+/// # Parameters
+/// Same as `search`, with `reader` as the source of the lines.
+///
+/// # Returns
+/// Yields each matching line with its 1-based line number, or the I/O error that interrupted the reading
+/// (e.g. invalid UTF-8).
+pub fn search_reader<'a, R: BufRead + 'a>(
+    matcher: &'a Matcher,
+    invert: bool,
+    reader: R,
+) -> impl Iterator<Item = io::Result<(usize, String)>> + 'a {
+    reader
+        .lines()
+        .enumerate()
+        .filter_map(move |(index, line)| match line {
+            Ok(line) if matcher.selects(&line, invert) => Some(Ok((index + 1, line))),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+}
+
+/// A query prepared once to match many lines (and files): the regular expression is compiled, or the literal
+/// lowercased, a single time.
+///
+/// # Examples
+/// ```
+/// use mgrep::Matcher;
+///
+/// let matcher = Matcher::new("rust", true, false, false).unwrap();
+///
+/// assert_eq!(vec![(1, "Rust:")], mgrep::search(&matcher, false, "Rust:\nPick three."));
+/// assert_eq!(vec![(2, "Trust me.")], mgrep::search(&matcher, false, "Go.\nTrust me."));
+/// ```
+pub struct Matcher {
+    // The literal query, already lowercase if `ignore_case` is set (unused in regex mode).
+    query: String,
+    ignore_case: bool,
+    // The compiled query, in regex mode.
+    pattern: Option<Regex>,
+    // The word characters when matches must be whole words (`-w`).
+    word: Option<WordChars>,
+    // Blank lines (empty or only whitespace) are never selected, whatever the query (`--skip-blank`).
+    skip_blank: bool,
+    // The query is in NFC, and lines are normalized to NFC before being matched (`--normalize`).
+    normalize: bool,
+    // The group of each regex match whose span is found, 0 being the whole match (`--capture`).
+    capture: usize,
+}
+
+impl Matcher {
+    /// Prepares a query for matching.
+    ///
+    /// # Parameters
+    /// - `query`: The text string to search for.
+    /// - `ignore_case`: A boolean indicating whether the search should be case insensitive.
+    /// - `regex`: A boolean indicating whether `query` is a regular expression rather than a literal.
+    /// - `word`: A boolean indicating whether `query` must match a whole word, bounded by non-word characters.
+    ///
+    /// # Returns
+    /// - `Ok(Matcher)`: The prepared query.
+    /// - `Err(MgrepError::InvalidRegex)`: If `regex` is set and `query` is not a valid pattern.
+    pub fn new(
+        query: &str,
+        ignore_case: bool,
+        regex: bool,
+        word: bool,
+    ) -> Result<Matcher, MgrepError> {
+        Matcher::build(
+            query,
+            ignore_case,
+            regex,
+            word.then_some(WordChars::Unicode),
+            None,
+        )
+    }
+
+    /// Prepares the query of a configuration: its patterns combined (see `Config::combined_query`),
+    /// with its case, regex and whole-word settings.
+    ///
+    /// # Returns
+    /// - `Ok(Matcher)`: The prepared query.
+    /// - `Err(MgrepError::InvalidRegex)`: If the combined query is not a valid pattern.
+    pub fn from_config(config: &Config) -> Result<Matcher, MgrepError> {
+        config.check_capture()?;
+
+        let (mut query, regex) = config.combined_query();
+        if config.normalize {
+            query = Cow::Owned(query.nfc().collect());
+        }
+
+        let mut matcher = Matcher::build(
+            &query,
+            config.ignore_case,
+            regex,
+            config.word_chars(),
+            config.multiline.then_some(config.multiline_dotall),
+        )?
+        .with_skip_blank(config.skip_blank);
+        matcher.normalize = config.normalize;
+        matcher.capture = config.capture;
+
+        Ok(matcher)
+    }
+
+    /// Sets whether blank lines (empty or only whitespace) are left out of the selected lines, even when the
+    /// query matches them (e.g. `.*`) or the search is inverted (`--skip-blank`).
+    pub fn with_skip_blank(mut self, skip_blank: bool) -> Matcher {
+        self.skip_blank = skip_blank;
+        self
+    }
+
+    // Same as `new`, with the word characters of `-w` (`--ascii-word`), and a regex matched across lines with
+    // `multiline` (see `build_regex`).
+    fn build(
+        query: &str,
+        ignore_case: bool,
+        regex: bool,
+        word: Option<WordChars>,
+        multiline: Option<bool>,
+    ) -> Result<Matcher, MgrepError> {
+        let pattern = if regex {
+            Some(build_regex(query, ignore_case, multiline)?)
+        } else {
+            None
+        };
+
+        // Convert the query to lowercase if the search is case insensitive, done once for efficiency.
+        let query = if ignore_case && !regex {
+            query.to_lowercase()
+        } else {
+            query.to_string()
+        };
+
+        Ok(Matcher {
+            query,
+            ignore_case,
+            pattern,
+            word,
+            skip_blank: false,
+            normalize: false,
+            capture: 0,
+        })
+    }
+
+    /// Checks whether `line` is selected: it contains the query (or doesn't, when `invert` is set) and isn't
+    /// a blank line left out (see `with_skip_blank`).
+    pub fn selects(&self, line: &str, invert: bool) -> bool {
+        !(self.skip_blank && line.trim().is_empty()) && self.is_match(line) != invert
+    }
+
+    /// Checks whether `line` contains the query (as a whole word, if required).
+    pub fn is_match(&self, line: &str) -> bool {
+        // The line is only checked (and copied) for normalization with `--normalize`, off the hot path otherwise.
+        match self.normalize.then(|| Normalized::new(line)).flatten() {
+            Some(normalized) => self.is_match_in(&normalized.text),
+            None => self.is_match_in(line),
+        }
+    }
+
+    // Same as `is_match`, on a line that's already normalized if needed.
+    fn is_match_in(&self, line: &str) -> bool {
+        // In regex mode every line is tested against the compiled pattern.
+        if let Some(pattern) = &self.pattern {
+            return pattern
+                .find_iter(line)
+                .any(|found| self.is_wanted(line, (found.start(), found.end())));
+        }
+
+        // For case-insensitive search, the line is compared in place to the lowercase query (see `find_literal`),
+        // so no lowercase copy of it is ever allocated. The matches are the same ones that get highlighted.
+        // `find_literal` never finds an empty query, while `contains` finds it everywhere.
+        if self.ignore_case && !self.query.is_empty() {
+            let mut start = 0;
+
+            while let Some(span) = find_literal(line, &self.query, true, start) {
+                if self.is_wanted(line, span) {
+                    return true;
+                }
+                start = span.1;
+            }
+
+            false
+        } else {
+            // For case-sensitive search, directly check if the line contains the query.
+            contains_query(line, &self.query, self.word)
+        }
+    }
+
+    /// Finds every match of the query in `line`, from left to right and without overlapping.
+    ///
+    /// # Returns
+    /// The byte span of each match in `line`. In word mode, occurrences inside larger words are left out.
+    /// With `--normalize`, the matches found in the normalized line are mapped back to `line`.
+    fn find_matches(&self, line: &str) -> Vec<(usize, usize)> {
+        match self.normalize.then(|| Normalized::new(line)).flatten() {
+            Some(normalized) => self
+                .find_matches_in(&normalized.text)
+                .into_iter()
+                .map(|span| normalized.to_original(span))
+                .collect(),
+            None => self.find_matches_in(line),
+        }
+    }
+
+    // Same as `find_matches`, on a line that's already normalized if needed.
+    fn find_matches_in(&self, line: &str) -> Vec<(usize, usize)> {
+        // In regex mode the spans come straight from the compiled pattern.
+        if let Some(pattern) = &self.pattern {
+            // The whole match must be a word for its group to count; a group without a part in it has no span.
+            if self.capture > 0 {
+                return pattern
+                    .captures_iter(line)
+                    .filter(|captures| {
+                        let found = captures.get_match();
+                        self.is_wanted(line, (found.start(), found.end()))
+                    })
+                    .filter_map(|captures| captures.get(self.capture))
+                    .map(|group| (group.start(), group.end()))
+                    .collect();
+            }
+
+            return pattern
+                .find_iter(line)
+                .map(|found| (found.start(), found.end()))
+                .filter(|&span| self.is_wanted(line, span))
+                .collect();
+        }
+
+        let mut spans = Vec::new();
+        let mut start = 0;
+
+        // The span is located in the original `line`, so it always lands on char boundaries,
+        // even when the lowercase form of the matched text has a different byte length.
+        while let Some(span) = find_literal(line, &self.query, self.ignore_case, start) {
+            if self.is_wanted(line, span) {
+                spans.push(span);
+            }
+            start = span.1;
+        }
+
+        spans
+    }
+
+    // Whether a match is kept: always, unless it must be a whole word and isn't.
+    fn is_wanted(&self, line: &str, (start, end): (usize, usize)) -> bool {
+        self.word
+            .is_none_or(|chars| is_word_bounded(line, start, end, chars))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The arguments of a command line, as `Config` parses them.
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    // Searches `input` with `config` like `run` does (colored only with `--color=always`), and returns the output.
+    fn render(config: &Config, input: &str) -> String {
+        render_with(config, None, input).1
+    }
+
+    // Same as `render` for the input of `filename`, also returning the number of selected lines.
+    fn render_with(config: &Config, filename: Option<&str>, input: &str) -> (usize, String) {
+        let matcher = Matcher::from_config(config).unwrap();
+        let color = config
+            .color
+            .should_color(false)
+            .then_some(config.highlight_color);
+        let mut out = Vec::new();
+        let count = print_matches(
+            config,
+            &matcher,
+            color,
+            filename,
+            input.as_bytes(),
+            &mut out,
+        )
+        .unwrap();
+        (count, String::from_utf8(out).unwrap())
+    }
+
+    // Tests the search for words in a case-sensitive manner.
+    #[test]
+    fn case_sensitive() {
+        let ignore_case = false;
+        let query = "duct";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        assert_eq!(
+            vec![(2, "safe, fast, productive.")],
+            search(
+                &Matcher::new(query, ignore_case, false, false).unwrap(),
+                false,
+                contents
+            )
+        );
+    }
+
+    // Tests the search for words in a case-insensitive manner.
+    #[test]
+    fn case_insensitive() {
+        let ignore_case = true;
+        let query = "rUsT";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        assert_eq!(
+            vec![(1, "Rust:"), (4, "Trust me.")],
+            search(
+                &Matcher::new(query, ignore_case, false, false).unwrap(),
+                false,
+                contents
+            )
+        );
+    }
+
+    // Tests the search with a regular expression instead of a literal query.
+    #[test]
+    fn regex_search() {
+        let query = r"\w+:$";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three:
+Duct tape.";
+
+        assert_eq!(
+            vec![(1, "Rust:"), (3, "Pick three:")],
+            search(
+                &Matcher::new(query, false, true, false).unwrap(),
+                false,
+                contents
+            )
+        );
+    }
+
+    // Tests that an invalid regular expression is reported instead of panicking.
+    #[test]
+    fn invalid_regex() {
+        assert!(Matcher::new("(unclosed", false, true, false).is_err());
+    }
+
+    // Tests that line numbers reflect the original position of each matching line.
+    #[test]
+    fn line_numbers() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        assert_eq!(
+            vec![(1, "Rust:"), (4, "Trust me.")],
+            search(
+                &Matcher::new("ust", false, false, false).unwrap(),
+                false,
+                contents
+            )
+        );
+    }
+
+    // Tests that inverted search selects the non-matching lines, in both case modes.
+    #[test]
+    fn invert_match() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        assert_eq!(
+            vec![
+                (2, "safe, fast, productive."),
+                (3, "Pick three."),
+                (4, "Trust me.")
+            ],
+            search(
+                &Matcher::new("Rust", false, false, false).unwrap(),
+                true,
+                contents
+            )
+        );
+        assert_eq!(
+            vec![(2, "safe, fast, productive."), (3, "Pick three.")],
+            search(
+                &Matcher::new("rUsT", true, false, false).unwrap(),
+                true,
+                contents
+            )
+        );
+    }
+
+    // Tests that whole-word search skips occurrences inside larger words, in both case modes.
+    #[test]
+    fn whole_word() {
+        let contents = "\
+The cat sat.
+A category of its own.
+Concatenate.
+CAT_NAP
+Cat!";
+
+        assert_eq!(
+            vec![(1, "The cat sat.")],
+            search(
+                &Matcher::new("cat", false, false, true).unwrap(),
+                false,
+                contents
+            )
+        );
+        assert_eq!(
+            vec![(1, "The cat sat."), (5, "Cat!")],
+            search(
+                &Matcher::new("cat", true, false, true).unwrap(),
+                false,
+                contents
+            )
+        );
+        assert_eq!(
+            vec![(1, "The cat sat."), (5, "Cat!")],
+            search(
+                &Matcher::new("c.t", true, true, true).unwrap(),
+                false,
+                contents
+            )
+        );
+    }
+
+    // Tests that accented letters are word characters with `-w`, unless `--ascii-word` is given.
+    #[test]
+    fn ascii_word() {
+        let contents = "un café noir\ncafé\nécafé";
+        let config =
+            Config::new("caf".to_string(), InputType::LiteralInput(String::new())).with_word(true);
+
+        // `é` is a letter, so `caf` is never a whole word in Unicode mode.
+        let output = render(&config, contents);
+        assert!(output.is_empty());
+
+        let config = config.with_ascii_word(true);
+        let output = render(&config, contents);
+        assert_eq!("un café noir\ncafé\nécafé\n", output);
+
+        // A whole word bordered by an accented letter is only one in ASCII mode.
+        let line = "écafé";
+        assert!(!is_word_bounded(line, 2, 7, WordChars::Unicode));
+        assert!(is_word_bounded(line, 2, 7, WordChars::Ascii));
+        assert!(is_word_bounded("un café noir", 3, 8, WordChars::Unicode));
+    }
+
+    // Tests that case-insensitive spans are located in the original line, on char boundaries.
+    #[test]
+    fn multibyte_case_insensitive_span() {
+        // Accented characters keep their byte length when lowercased.
+        assert_eq!(Some((0, 5)), find_literal("Café au lait", "café", true, 0));
+        assert_eq!(Some((7, 12)), find_literal("Un bon CAFÉ", "café", true, 2));
+
+        // 'ẞ' is 3 bytes while its lowercase 'ß' is 2, so the span is longer than the query.
+        assert_eq!(
+            Some((4, 11)),
+            find_literal("Die GROẞE Stadt", "große", true, 0)
+        );
+        assert_eq!("GROẞE", &"Die GROẞE Stadt"[4..11]);
+    }
+
+    // Tests that the ASCII fast path ignores case without changing the spans.
+    #[test]
+    fn ascii_case_insensitive_span() {
+        assert_eq!(
+            Some((4, 15)),
+            find_literal("The QUICK Brown fox", "quick brown", true, 0)
+        );
+        assert_eq!(Some((3, 6)), find_literal("FoxFOX", "fox", true, 1));
+        assert_eq!(None, find_literal("Fo", "fox", true, 0));
+        assert_eq!(None, find_ascii_ignore_case(b"abc", b""));
+    }
+
+    // Tests that a lowercase expansion ending mid-character isn't reported as a match.
+    #[test]
+    fn partial_lowercase_expansion() {
+        // 'İ' lowercases to "i̇" (two chars), so "i" alone doesn't cover a whole character.
+        assert_eq!(None, find_literal("İ", "i", true, 0));
+        assert_eq!(
+            Some((0, 2)),
+            find_literal("İ", &"İ".to_lowercase(), true, 0)
+        );
+    }
+
+    // Tests that the context flags accept both separate and inline values, and `-A`/`-B` override `-C`.
+    #[test]
+    fn context_values() {
+        let args: Vec<String> = ["-C", "3", "-A", "1", "--before-context=2"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        assert_eq!(
+            Some(3),
+            Config::get_number(&args, "-C", "--context").unwrap()
+        );
+        assert_eq!(
+            Some(1),
+            Config::get_number(&args, "-A", "--after-context").unwrap()
+        );
+        assert_eq!(
+            Some(2),
+            Config::get_number(&args, "-B", "--before-context").unwrap()
+        );
+        assert!(Config::get_number(
+            &["-A".to_string(), "x".to_string()],
+            "-A",
+            "--after-context"
+        )
+        .is_err());
+    }
+
+    // Tests that a matching line is serialized with its byte spans.
+    #[test]
+    fn json_output() {
+        let line = "safe, fast, productive.";
+        let spans = Matcher::new("fast", false, false, false)
+            .unwrap()
+            .find_matches(line);
+
+        assert_eq!(
+            r#"{"file":"poem.txt","line_number":2,"line":"safe, fast, productive.","matches":[{"start":6,"end":10}]}"#,
+            json_line(Some("poem.txt"), 2, line, &spans)
+        );
+        assert_eq!(
+            r#"{"file":null,"line_number":1,"line":"","matches":[]}"#,
+            json_line(None, 1, "", &[])
+        );
+    }
+
+    // Tests that JSON spans and columns count characters, not bytes, on multibyte lines.
+    #[test]
+    fn char_offsets() {
+        let line = "héllo match";
+        let spans = Matcher::new("match", false, false, false)
+            .unwrap()
+            .find_matches(line);
+
+        assert_eq!(vec![(7, 12)], spans);
+        assert_eq!(6, char_offset(line, 7));
+        assert_eq!(7, column_of(line, 7));
+        assert_eq!(
+            r#"{"file":null,"line_number":1,"line":"héllo match","matches":[{"start":6,"end":11}]}"#,
+            json_line(None, 1, line, &spans)
+        );
+    }
+
+    // Tests that quotes, backslashes and control characters are escaped.
+    #[test]
+    fn json_escaping() {
+        assert_eq!(
+            r#"say \"hi\" C:\\dir\ttab\u0007bell"#,
+            json_escape("say \"hi\" C:\\dir\ttab\u{7}bell")
+        );
+    }
+
+    // Tests that `-F` matches regex metacharacters literally, even with `-e`.
+    #[test]
+    fn fixed_strings() {
+        let config = Config::new("a.b".to_string(), InputType::LiteralInput(String::new()))
+            .with_regex(true)
+            .with_fixed_strings(true);
+        let (query, regex) = config.combined_query();
+
+        assert!(!regex);
+        assert!(search(
+            &Matcher::new(&query, false, regex, false).unwrap(),
+            false,
+            "axb"
+        )
+        .is_empty());
+        assert_eq!(
+            vec![(1, "a.b")],
+            search(
+                &Matcher::new(&query, false, regex, false).unwrap(),
+                false,
+                "a.b"
+            )
+        );
+    }
+
+    // Tests that blank lines are left out even when the query matches them or the search is inverted.
+    #[test]
+    fn skipped_blank_lines() {
+        let contents = "first\n\n  \t\nlast";
+        let any = Matcher::new(".*", false, true, false).unwrap();
+        assert_eq!(4, search(&any, false, contents).len());
+
+        let any = any.with_skip_blank(true);
+        assert_eq!(
+            vec![(1, "first"), (4, "last")],
+            search(&any, false, contents)
+        );
+
+        let first = Matcher::new("first", false, false, false)
+            .unwrap()
+            .with_skip_blank(true);
+        assert_eq!(vec![(4, "last")], search(&first, true, contents));
+    }
+
+    // Tests that `--normalize` matches a precomposed query against decomposed text (and the other way round),
+    // and highlights the original characters.
+    #[test]
+    fn normalized_matching() {
+        let decomposed = "un cafe\u{301} noir\n";
+        let config = Config::new("café".to_string(), InputType::LiteralInput(String::new()));
+        assert!(search(&Matcher::from_config(&config).unwrap(), false, decomposed).is_empty());
+
+        let config = config.with_normalize(true);
+        let matcher = Matcher::from_config(&config).unwrap();
+        assert_eq!(
+            vec![(1, "un cafe\u{301} noir")],
+            search(&matcher, false, decomposed)
+        );
+        assert_eq!(vec![(3, 9)], matcher.find_matches("un cafe\u{301} noir"));
+
+        let config = Config::new(
+            "cafe\u{301}".to_string(),
+            InputType::LiteralInput(String::new()),
+        )
+        .with_normalize(true);
+        let matcher = Matcher::from_config(&config).unwrap();
+        assert_eq!(
+            vec![(1, "un café noir")],
+            search(&matcher, false, "un café noir")
+        );
+    }
+
+    // Tests that `--vimgrep` prints a line per match, each with its column and the whole line, unhighlighted.
+    #[test]
+    fn vimgrep_lines() {
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+            .with_vimgrep(true)
+            .with_color(ColorChoice::Always);
+        let input = "no match\na fox and a fox\n";
+
+        let (count, output) = render_with(&config, Some("poem.txt"), input);
+
+        assert_eq!(1, count);
+        assert_eq!(
+            "poem.txt:2:3:a fox and a fox\npoem.txt:2:13:a fox and a fox\n",
+            output
+        );
+    }
+
+    // Tests that `--ratio` follows the count with the number of lines searched and the percentage selected,
+    // counting only the lines read before `-m` stops the search.
+    #[test]
+    fn count_ratio() {
+        let input = "fox
+2
+3
+fox
+5
+6
+7
+fox
+9
+10
+";
+        let ratio_of = |config: &Config| render(config, input);
+
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+            .with_count(true)
+            .with_ratio(true);
+        assert_eq!("3/10 (30.0%)\n", ratio_of(&config));
+        assert_eq!("2/4 (50.0%)\n", ratio_of(&config.with_max_count(Some(2))));
+    }
+
+    // Tests that `--capture` highlights only the given group of each match, leaving the rest of it plain, and
+    // that a group the pattern doesn't have, or a literal query, is an error when the arguments are parsed.
+    #[test]
+    fn captured_groups() {
+        let config = Config::new(
+            r"(\d+)-(\d+)".to_string(),
+            InputType::LiteralInput(String::new()),
+        )
+        .with_regex(true)
+        .with_capture(2)
+        .with_color(ColorChoice::Always);
+        let input = "pages 10-20 and 30-40\nno range\n";
+
+        let (count, output) = render_with(&config, None, input);
+
+        assert_eq!(1, count);
+        assert_eq!(
+            "pages 10-\x1b[31m20\x1b[0m and 30-\x1b[31m40\x1b[0m\n",
+            output
+        );
+
+        let build = Config::build_from_args;
+        assert!(build(&[r"(\d+)-(\d+)", "Cargo.toml", "-e", "--capture=2"]).is_ok());
+        assert!(matches!(
+            build(&[r"(\d+)-(\d+)", "Cargo.toml", "-e", "--capture=3"]),
+            Err(MgrepError::InvalidValue { flag, value, .. }) if flag == "--capture" && value == "3"
+        ));
+        assert!(matches!(
+            build(&["fox", "Cargo.toml", "--capture=1"]),
+            Err(MgrepError::Usage(_))
+        ));
+    }
+
+    // Tests that the byte search matches lines holding invalid UTF-8, on either side of the query, that only
+    // ASCII letters are case-folded, and that `\r\n` line endings are dropped.
+    #[test]
+    fn byte_search() {
+        let contents: &[u8] =
+            b"ok \xff\xfe fox\r\nno match \xc3\nFOX\x80\n\xc3\xa9t\xc3\xa9 FOX\n\xc9T\xc9\n";
+
+        assert_eq!(
+            vec![&b"ok \xff\xfe fox"[..]],
+            search_bytes(b"fox", false, contents)
+        );
+        assert_eq!(
+            vec![
+                &b"ok \xff\xfe fox"[..],
+                b"FOX\x80",
+                b"\xc3\xa9t\xc3\xa9 FOX"
+            ],
+            search_bytes(b"Fox", true, contents)
+        );
+        // An invalid query is matched byte for byte, and `\xe9`-like bytes aren't case-folded.
+        assert_eq!(
+            vec![&b"no match \xc3"[..]],
+            search_bytes(b"match \xc3", false, contents)
+        );
+        assert_eq!(
+            vec![&b"\xc9T\xc9"[..]],
+            search_bytes(b"\xc9t", true, contents)
+        );
+        assert!(search_bytes(b"\xe9t", true, contents).is_empty());
+        assert!(search_bytes(b"fox", false, b"").is_empty());
+        assert_eq!(5, search_bytes(b"", true, contents).len());
+    }
+
+    // Tests that `-m` stops the count at N lines with `-c`, and that `--count-matches` only counts the
+    // occurrences in those lines.
+    #[test]
+    fn max_count_caps_count() {
+        let input = "fox and fox\n".repeat(10);
+        let count_of = |config: &Config| render_with(config, None, &input);
+
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+            .with_max_count(Some(3));
+        assert_eq!(
+            (3, "3\n".to_string()),
+            count_of(&config.clone().with_count(true))
+        );
+        assert_eq!(
+            (3, "6\n".to_string()),
+            count_of(&config.with_count_matches(true))
+        );
+    }
+
+    // Tests that `--passthru` prints every line, highlighting only the matches, and counts only the matching lines.
+    #[test]
+    fn passthru_lines() {
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+            .with_passthru(true)
+            .with_color(ColorChoice::Always);
+        let input = "first\na fox\nmiddle\nfox and fox\nlast\n";
+
+        let (count, output) = render_with(&config, None, input);
+
+        assert_eq!(2, count);
+        assert_eq!(
+            "first\na \x1b[31mfox\x1b[0m\nmiddle\n\x1b[31mfox\x1b[0m and \x1b[31mfox\x1b[0m\nlast\n",
+            output
+        );
+    }
+
+    // Tests that matching lines are prefixed with `file:line:` and context lines with `file-line-`.
+    #[test]
+    fn context_separators() {
+        let files = vec!["poem.txt".to_string(), "other.txt".to_string()];
+        let config = Config::new("fox".to_string(), InputType::FilePaths(files))
+            .with_before_context(1)
+            .with_after_context(1)
+            .with_line_number(true);
+        let input = "first\na fox\nmiddle\nfox again\nlast\n";
+
+        let output = render_with(&config, Some("poem.txt"), input).1;
+
+        assert_eq!(
+            "poem.txt-1-first\npoem.txt:2:a fox\npoem.txt-3-middle\npoem.txt:4:fox again\npoem.txt-5-last\n",
+            output
+        );
+    }
+
+    // Tests that `--dedupe` prints consecutive identical matches once, while still counting them,
+    // and prints a repeated line again once another line came in between.
+    #[test]
+    fn deduped_lines() {
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+            .with_dedupe(true)
+            .with_line_number(true);
+        let input = "a fox\na fox\na fox\nother fox\nno\nother fox\n";
+
+        let (count, output) = render_with(&config, None, input);
+
+        assert_eq!(5, count);
+        assert_eq!("1:a fox\n4:other fox\n6:other fox\n", output);
+    }
+
+    // Tests that `--trim` only strips the printed lines, and highlights the matches at their new place.
+    #[test]
+    fn trimmed_lines() {
+        let config = Config::new("fox ".to_string(), InputType::LiteralInput(String::new()))
+            .with_trim(true)
+            .with_color(ColorChoice::Always)
+            .with_after_context(1);
+        let input = "\t  the fox \t\n  context  \nfox\n";
+
+        let output = render(&config, input);
+
+        assert_eq!("the \x1b[31mfox\x1b[0m\ncontext\n", output);
+    }
+
+    // Tests that `--max-columns` cuts long lines after N characters, highlighting only the matches before the
+    // cut, and that a line matching only past the cut is still selected.
+    #[test]
+    fn truncated_lines() {
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+            .with_max_columns(Some(80))
+            .with_color(ColorChoice::Always);
+        let early = format!("{}fox{}", "a".repeat(77), "b".repeat(420));
+        let late = format!("{}fox{}", "a".repeat(100), "b".repeat(397));
+        let short = "the fox";
+        let input = format!("{early}\n{late}\n{short}\n");
+
+        let (count, output) = render_with(&config, None, &input);
+
+        assert_eq!(3, count);
+        assert_eq!(
+            format!(
+                "{}\x1b[31mfox\x1b[0m [... omitted 420 chars]\n{} [... omitted 420 chars]\nthe \x1b[31mfox\x1b[0m\n",
+                "a".repeat(77),
+                "a".repeat(80)
+            ),
+            output
+        );
+    }
+
+    // Tests that `--column` counts characters, not bytes, up to the first match.
+    #[test]
+    fn column_numbers() {
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+            .with_line_number(true)
+            .with_column(true);
+        let input = "no match\nçà fox and fox\n";
+
+        let output = render(&config, input);
+
+        assert_eq!("2:4:çà fox and fox\n", output);
+    }
+
+    // Tests that every pair of `CONFLICTS` is rejected (in any form and order), and that compatible flags pass.
+    #[test]
+    fn flag_conflicts() {
+        for ((short, long), (other_short, other_long)) in CONFLICTS {
+            assert!(Config::check_conflicts(&args(&["fox", short, other_long])).is_err());
+            assert!(Config::check_conflicts(&args(&[other_short, long, "fox"])).is_err());
+        }
+        assert!(Config::check_conflicts(&args(&["fox", "-U", "--replace=bar"])).is_err());
+
+        let err = Config::check_conflicts(&args(&["fox", "-v", "-o"])).unwrap_err();
+        assert_eq!(
+            "-o (--only-matching) and -v (--invert-match) can't be used together",
+            err.to_string()
+        );
+        let err = Config::check_conflicts(&args(&["fox", "--json", "-c"])).unwrap_err();
+        assert_eq!(
+            "-c (--count) and --json can't be used together",
+            err.to_string()
+        );
+
+        for valid in [
+            &["fox", "-c", "-v"][..],
+            &["fox", "-o", "-n", "-m", "2"],
+            &["fox", "-l", "-v"],
+            &["fox", "--count-matches", "-w"],
+            &["fox", "--json", "-v"],
+            &["fox", "-q", "-c"],
+        ] {
+            assert!(Config::check_conflicts(&args(valid)).is_ok());
+        }
+    }
+
+    // Tests that each failure is reported by its own `MgrepError` variant, which keeps the message of `main`.
+    #[test]
+    fn error_variants() {
+        let build = Config::build_from_args;
+
+        assert!(matches!(build(&["-n"]), Err(MgrepError::MissingQuery)));
+        assert!(matches!(
+            build(&["fox", "--highlight-color=purple"]),
+            Err(MgrepError::UnknownColor(color)) if color == "purple"
+        ));
+        assert!(matches!(
+            build(&["fox", "--sort=size"]),
+            Err(MgrepError::InvalidValue { flag, value, .. }) if flag == "--sort" && value == "size"
+        ));
+
+        let err = Matcher::new("(unclosed", false, true, false).err().unwrap();
+        assert!(matches!(&err, MgrepError::InvalidRegex(query, _) if query == "(unclosed"));
+        assert!(err
+            .to_string()
+            .starts_with("Invalid regular expression '(unclosed'"));
+
+        let config = Config::new(
+            "fox".to_string(),
+            InputType::FilePath("missing/input.txt".to_string()),
+        );
+        assert!(matches!(
+            run(config),
+            Err(MgrepError::FileRead(err, _)) if err.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    // Tests that `--count-matches` counts every occurrence, not only the lines, in both search modes.
+    #[test]
+    fn count_matches() {
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+            .with_count_matches(true)
+            .with_ignore_case(true);
+        let input = "a fox and a FOX
+no match
+";
+
+        let (count, output) = render_with(&config, None, input);
+
+        // The exit status still depends on the selected lines.
+        assert_eq!(1, count);
+        assert_eq!("2\n", output);
+
+        let config = config
+            .with_patterns(vec![r"f.x".to_string()])
+            .with_regex(true);
+        let output = render(&config, input);
+
+        assert_eq!("2\n", output);
+    }
+
+    // Tests that `-R` replaces every match of a line verbatim, located case-insensitively with `-i`.
+    #[test]
+    fn replace_matches() {
+        let config = Config::new("foo".to_string(), InputType::LiteralInput(String::new()))
+            .with_replace(Some("$bar".to_string()));
+        let input = "a foo here
+nothing
+foo, Foo and foo
+";
+
+        let output = render(&config, input);
+        assert_eq!("a $bar here\n$bar, Foo and $bar\n", output);
+
+        let config = config.with_ignore_case(true);
+        let output = render(&config, input);
+        assert_eq!("a $bar here\n$bar, $bar and $bar\n", output);
+    }
+
+    // Tests that a leading BOM and CRLF line endings are neither matched nor printed.
+    #[test]
+    fn bom_and_crlf() {
+        let contents = "\u{feff}Rust:\r\nsafe, fast, productive.\r\nTrust me.\r";
+
+        assert_eq!(
+            vec![(1, "Rust:"), (3, "Trust me.")],
+            search(
+                &Matcher::new("rust", true, false, false).unwrap(),
+                false,
+                contents
+            )
+        );
+        assert_eq!(
+            vec![(1, "Rust:")],
+            search(
+                &Matcher::new("^Rust:$", false, true, false).unwrap(),
+                false,
+                contents
+            )
+        );
+
+        let config = Config::new("Rust:".to_string(), InputType::LiteralInput(String::new()))
+            .with_anchor_start(true)
+            .with_anchor_end(true);
+        let output = render(&config, contents);
+
+        assert_eq!("Rust:\n", output);
+    }
+
+    // Tests that `-b` prints the offset of each line's start (counting the BOM and CRLF endings),
+    // and of each match with `-o`.
+    #[test]
+    fn byte_offsets() {
+        let contents = "\u{feff}one fox\r\ntwo\nfox and fox\n";
+        let offsets = |only_matching: bool| {
+            let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+                .with_byte_offset(true)
+                .with_line_number(true)
+                .with_only_matching(only_matching);
+            render(&config, contents)
+        };
+
+        assert_eq!("1:3:one fox\n3:16:fox and fox\n", offsets(false));
+        assert_eq!("1:7:fox\n3:16:fox\n3:24:fox\n", offsets(true));
+    }
+
+    // Tests that with `-z` the records are split on NUL bytes, and printed NUL-terminated.
+    #[test]
+    fn null_data_records() {
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()))
+            .with_null_data_input(true);
+        let input = "quick brown fox\njumps\0lazy dog\0fox again\0";
+
+        let (count, output) = render_with(&config, None, input);
+
+        assert_eq!(2, count);
+        assert_eq!("quick brown fox\njumps\0fox again\0", output);
+    }
+
+    // Tests that binary inputs are detected, and reported or skipped instead of printed.
+    #[test]
+    fn binary_files() {
+        assert!(is_binary(b"text\0more", false));
+        assert!(!is_binary(b"text\0more", true));
+        assert!(is_binary(b"\xff\xfe", false));
+        assert!(!is_binary(
+            "cut \u{e9}".as_bytes().split_last().unwrap().1,
+            false
+        ));
+
+        let input = "header\0\nneedle here\n";
+        let search_binary = |binary_files: BinaryFiles| {
+            let config = Config::new("needle".to_string(), InputType::LiteralInput(String::new()))
+                .with_binary_files(binary_files);
+            render_with(&config, Some("data.bin"), input)
+        };
+
+        assert_eq!(
+            (1, "Binary file data.bin matches\n".to_string()),
+            search_binary(BinaryFiles::Binary)
+        );
+        assert_eq!((0, String::new()), search_binary(BinaryFiles::WithoutMatch));
+        assert_eq!(
+            (1, "needle here\n".to_string()),
+            search_binary(BinaryFiles::Text)
+        );
+    }
+
+    // Tests the exact bytes of a highlighted line, and that a line without match is left unchanged.
+    #[test]
+    fn highlighting() {
+        let red = Some(HighlightColor::Red);
+        let highlight = |query: &str, color: Option<HighlightColor>, line: &str| {
+            let mut out = Vec::new();
+            let matcher = Matcher::new(query, false, false, false).unwrap();
+            write_highlighted(&mut out, color, line, &matcher.find_matches(line)).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            "before\x1b[31mMATCH\x1b[0mafter",
+            highlight("MATCH", red, "beforeMATCHafter")
+        );
+        assert_eq!("no match here", highlight("MATCH", red, "no match here"));
+        assert_eq!(
+            "beforeMATCHafter",
+            highlight("MATCH", None, "beforeMATCHafter")
+        );
+    }
+
+    // Tests that overlapping occurrences are highlighted left to right, without overlapping.
+    #[test]
+    fn overlapping_highlight() {
+        let spans = Matcher::new("aa", false, false, false)
+            .unwrap()
+            .find_matches("aaaaa");
+        assert_eq!(vec![(0, 2), (2, 4)], spans);
+
+        let mut out = Vec::new();
+        write_highlighted(&mut out, Some(HighlightColor::Red), "aaaaa", &spans).unwrap();
+        assert_eq!(
+            "\x1b[31maa\x1b[0m\x1b[31maa\x1b[0ma",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    // Tests that `NO_COLOR` disables highlighting, unless `--color` is given explicitly.
+    #[test]
+    fn no_color() {
+        let always = args(&["--color=always"]);
+
+        assert_eq!(ColorChoice::Never, Config::get_color(&[], true).unwrap());
+        assert_eq!(
+            ColorChoice::Always,
+            Config::get_color(&always, true).unwrap()
+        );
+
+        assert_eq!(ColorChoice::Auto, Config::get_color(&[], false).unwrap());
+        assert_eq!(
+            ColorChoice::Always,
+            Config::get_color(&always, false).unwrap()
+        );
+    }
+
+    // Tests that the highlight colors map to their SGR codes, and that unknown names are rejected.
+    #[test]
+    fn highlight_color() {
+        assert_eq!("\x1b[32m", HighlightColor::parse("green").unwrap().sgr());
+        assert_eq!("\x1b[1m", HighlightColor::parse("bold").unwrap().sgr());
+        assert!(HighlightColor::parse("purple").is_err());
+
+        let matcher = Matcher::new("fox", false, false, false).unwrap();
+        let mut out = Vec::new();
+        write_highlighted(
+            &mut out,
+            Some(HighlightColor::Cyan),
+            "a fox",
+            &matcher.find_matches("a fox"),
+        )
+        .unwrap();
+        assert_eq!("a \x1b[36mfox\x1b[0m", String::from_utf8(out).unwrap());
+    }
+
+    // Tests the SGR codes of each part of a colored prefix, of a context line's, and of a count's, and that
+    // `MGREP_COLORS` overrides them like `GREP_COLORS`.
+    #[test]
+    fn prefix_colors() {
+        let files = vec!["poem.txt".to_string(), "other.txt".to_string()];
+        let config = Config::new("fox".to_string(), InputType::FilePaths(files))
+            .with_after_context(1)
+            .with_line_number(true)
+            .with_column(true)
+            .with_color(ColorChoice::Always);
+        let print = |config: &Config| render_with(config, Some("poem.txt"), "a fox\nlast\n").1;
+
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m1\x1b[0m\x1b[36m:\x1b[0m\
+            \x1b[32m3\x1b[0m\x1b[36m:\x1b[0ma \x1b[31mfox\x1b[0m\n\
+            \x1b[35mpoem.txt\x1b[0m\x1b[36m-\x1b[0m\x1b[32m2\x1b[0m\x1b[36m-\x1b[0mlast\n",
+            print(&config)
+        );
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m1\n",
+            print(&config.clone().with_count(true))
+        );
+
+        let colors = PrefixColors::parse("mt=01;31:fn=34:ln=01;33:se=:bn=red");
+        assert_eq!("34", colors.filename);
+        assert_eq!("01;33", colors.line_number);
+        assert_eq!("32", colors.byte_offset);
+        assert_eq!("", colors.separator);
+        assert_eq!(
+            "\x1b[34mpoem.txt\x1b[0m:\x1b[01;33m1\x1b[0m:\x1b[01;33m3\x1b[0m:a \x1b[31mfox\x1b[0m\n\
+            \x1b[34mpoem.txt\x1b[0m-\x1b[01;33m2\x1b[0m-last\n",
+            print(&config.with_prefix_colors(colors))
+        );
+    }
+
+    // Tests that each `--color` mode turns highlighting on or off.
+    #[test]
+    fn color_choice() {
+        assert!(ColorChoice::parse("always").unwrap().should_color(false));
+        assert!(!ColorChoice::parse("never").unwrap().should_color(true));
+        assert!(ColorChoice::parse("auto").unwrap().should_color(true));
+        assert!(!ColorChoice::parse("auto").unwrap().should_color(false));
+        assert!(ColorChoice::parse("sometimes").is_err());
+    }
+
+    // Tests that the streaming search yields the same lines as the in-memory one.
+    #[test]
+    fn streaming_search() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        let streamed: Vec<(usize, String)> = search_reader(
+            &Matcher::new("rUsT", true, false, false).unwrap(),
+            false,
+            contents.as_bytes(),
+        )
+        .collect::<io::Result<_>>()
+        .unwrap();
+
+        assert_eq!(
+            vec![(1, "Rust:".to_string()), (4, "Trust me.".to_string())],
+            streamed
+        );
+    }
+
+    // Generates `remaining` bytes of text on the fly, one matching line in every thousand.
+    struct SyntheticInput {
+        remaining: u64,
+        line: u64,
+    }
+
+    impl Read for SyntheticInput {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut written = 0;
+
+            while self.remaining > 0 && buf.len() - written >= 64 {
+                let text = if self.line.is_multiple_of(1000) {
+                    format!("{:>62}\n", format!("needle {}", self.line))
+                } else {
+                    format!("{:>62}\n", self.line)
+                };
+                buf[written..written + text.len()].copy_from_slice(text.as_bytes());
+
+                written += text.len();
+                self.remaining = self.remaining.saturating_sub(text.len() as u64);
+                self.line += 1;
+            }
+
+            Ok(written)
+        }
+    }
+
+    // Tests that streaming a 2 GB input keeps the peak memory far below the input size.
+    #[test]
+    #[ignore = "slow: streams 2 GB of synthetic input"]
+    #[cfg(target_os = "linux")]
+    fn streaming_memory_is_bounded() {
+        let input = SyntheticInput {
+            remaining: 2 * 1024 * 1024 * 1024,
+            line: 0,
+        };
+
+        let matches = search_reader(
+            &Matcher::new("needle", false, false, false).unwrap(),
+            false,
+            BufReader::new(input),
+        )
+        .map(Result::unwrap)
+        .count();
+
+        // Peak resident set size of this process, in kB.
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let peak_kb: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmHWM:"))
+            .and_then(|value| value.split_whitespace().next())
+            .and_then(|value| value.parse().ok())
+            .unwrap();
+
+        assert!(matches > 30_000);
+        assert!(peak_kb < 100 * 1024, "peak memory was {peak_kb} kB");
+    }
+
+    // Tests that smart case ignores case only for an all-lowercase query, and that explicit flags override it.
+    #[test]
+    fn smart_case() {
+        assert!(Config::get_ignore_case(&args(&["-S"]), "rust", None));
+        assert!(!Config::get_ignore_case(&args(&["-S"]), "Rust", None));
+        assert!(Config::get_ignore_case(
+            &args(&["--smart-case", "-i"]),
+            "Rust",
+            None
+        ));
+        assert!(!Config::get_ignore_case(
+            &args(&["-S", "-ni"]),
+            "rust",
+            None
+        ));
+    }
+
+    // Tests the `--max-filesize` values, with and without a suffix.
+    #[test]
+    fn max_filesize_values() {
+        assert_eq!(512, Config::parse_size("512").unwrap());
+        assert_eq!(10 * 1024, Config::parse_size("10K").unwrap());
+        assert_eq!(2 * 1024 * 1024, Config::parse_size("2M").unwrap());
+        assert_eq!(1 << 30, Config::parse_size("1g").unwrap());
+        assert!(Config::parse_size("5Z").is_err());
+        assert!(Config::parse_size("M").is_err());
+        assert!(Config::parse_size("99999999999999999999G").is_err());
+    }
+
+    // Tests that a search cancelled after its timeout stops before the next line, and writes nothing more.
+    #[test]
+    fn cancelled_search() {
+        let config = Config::new("fox".to_string(), InputType::LiteralInput(String::new()));
+        let matcher = Matcher::from_config(&config).unwrap();
+        config.cancelled.store(true, Ordering::Relaxed);
+
+        let mut out = Vec::new();
+        let input: &[u8] = b"a fox\n";
+        assert!(print_matches(&config, &matcher, None, None, input, &mut out).is_err());
+        assert!(out.is_empty());
+
+        let mut writer = Cancellable {
+            inner: Vec::new(),
+            cancelled: Arc::clone(&config.cancelled),
+        };
+        assert!(writer.write_all(b"a fox\n").is_err());
+        assert!(writer.inner.is_empty());
+    }
+
+    // Tests that `--timeout` takes milliseconds, seconds or minutes (seconds without a unit), and rejects
+    // zero or unknown units.
+    #[test]
+    fn timeout_values() {
+        assert_eq!(
+            Duration::from_millis(500),
+            Config::parse_duration("500ms").unwrap()
+        );
+        assert_eq!(
+            Duration::from_secs(5),
+            Config::parse_duration("5s").unwrap()
+        );
+        assert_eq!(
+            Duration::from_secs(120),
+            Config::parse_duration("2m").unwrap()
+        );
+        assert_eq!(Duration::from_secs(3), Config::parse_duration("3").unwrap());
+        assert!(Config::parse_duration("0s").is_err());
+        assert!(Config::parse_duration("5h").is_err());
+        assert!(Config::parse_duration("ms").is_err());
+    }
+
+    // Tests that without a path, a terminal stdin is reported instead of waiting for typed input,
+    // while a path is still searched.
+    #[test]
+    fn terminal_stdin() {
+        let err = Config::get_input(&args(&["fox", "-n"]), Some(0), true)
+            .err()
+            .unwrap();
+        assert!(matches!(&err, MgrepError::Usage(message) if message.contains("add - to type it")));
+        assert!(Config::get_input(&args(&["fox", "--line-buffered"]), Some(0), true).is_err());
+        assert!(matches!(
+            Config::get_input(&args(&["fox", "Cargo.toml"]), Some(0), true),
+            Ok(InputType::FilePath(path)) if path == "Cargo.toml"
+        ));
+    }
+
+    // Tests that an empty or blank query is rejected, unless `--allow-empty` makes it match every line.
+    #[test]
+    fn empty_query() {
+        assert!(matches!(
+            Config::get_query(&args(&["", "poem.txt"])),
+            Err(MgrepError::EmptyQuery)
+        ));
+        assert!(matches!(
+            Config::get_query(&args(&["  ", "-n"])),
+            Err(MgrepError::EmptyQuery)
+        ));
+        assert_eq!(
+            (1, ""),
+            Config::get_query(&args(&["-n", "", "--allow-empty"])).unwrap()
+        );
+
+        assert_eq!(
+            vec![(1, "Rust:"), (2, ""), (3, "Trust me.")],
+            search(
+                &Matcher::new("", false, false, false).unwrap(),
+                false,
+                "Rust:\n\nTrust me."
+            )
+        );
+        assert_eq!(
+            3,
+            search(
+                &Matcher::new("", true, false, false).unwrap(),
+                false,
+                "Rust:\n\nTrust me."
+            )
+            .len()
+        );
+    }
+
+    // Tests that a programmatic configuration starts with every option disabled.
+    #[test]
+    fn builder_defaults() {
+        let config = Config::new("to".to_string(), InputType::LiteralInput(String::new()))
+            .with_invert(true)
+            .with_after_context(2);
+
+        assert!(!config.ignore_case && !config.regex && !config.count);
+        assert!(config.invert);
+        assert_eq!((2, 0), (config.after, config.before));
+        assert_eq!(ColorChoice::Auto, config.color);
+    }
+
+    // Tests that a pattern file ignores blank lines, and that a missing one is an error.
+    #[test]
+    fn pattern_file() {
+        let path = std::env::temp_dir().join(format!("mgrep-patterns-{}", std::process::id()));
+        fs::write(&path, "fast\n\n   \nthree\n").unwrap();
+
+        assert_eq!(
+            vec!["fast", "three"],
+            Config::get_pattern_file(path.to_str().unwrap()).unwrap()
+        );
+        assert!(matches!(
+            Config::get_pattern_file("missing/patterns.txt"),
+            Err(MgrepError::FileRead(_, path)) if path == Path::new("missing/patterns.txt")
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    // Tests that a line matching any of several patterns is selected.
+    #[test]
+    fn multiple_patterns() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape (a.k.a. three.).";
+        let config = Config::new(String::new(), InputType::LiteralInput(String::new()))
+            .with_patterns(vec!["fast".to_string(), "three.".to_string()]);
+        let (query, regex) = config.combined_query();
+
+        // Literal patterns are escaped, so the '.' doesn't match any character.
+        assert_eq!(
+            vec![
+                (2, "safe, fast, productive."),
+                (3, "Pick three."),
+                (4, "Duct tape (a.k.a. three.).")
+            ],
+            search(
+                &Matcher::new(&query, false, regex, false).unwrap(),
+                false,
+                contents
+            )
+        );
+
+        let config = config.with_patterns(Vec::new());
+        let (query, regex) = config.combined_query();
+        assert!(search(
+            &Matcher::new(&query, false, regex, false).unwrap(),
+            false,
+            contents
+        )
+        .is_empty());
+    }
+
+    // Tests every combination of `--line-start` and `--line-end`, with a literal query containing regex syntax.
+    #[test]
+    fn line_anchors() {
+        let contents = "\
+a.b
+a.b and more
+more a.b
+A.B
+axb";
+        let anchored = |anchor_start: bool, anchor_end: bool, ignore_case: bool| {
+            let config = Config::new("a.b".to_string(), InputType::LiteralInput(String::new()))
+                .with_anchor_start(anchor_start)
+                .with_anchor_end(anchor_end);
+            let (query, regex) = config.combined_query();
+            search(
+                &Matcher::new(&query, ignore_case, regex, false).unwrap(),
+                false,
+                contents,
+            )
+        };
+
+        assert_eq!(
+            vec![(1, "a.b"), (2, "a.b and more"), (3, "more a.b")],
+            anchored(false, false, false)
+        );
+        assert_eq!(
+            vec![(1, "a.b"), (2, "a.b and more")],
+            anchored(true, false, false)
+        );
+        assert_eq!(
+            vec![(1, "a.b"), (3, "more a.b")],
+            anchored(false, true, false)
+        );
+        assert_eq!(vec![(1, "a.b")], anchored(true, true, false));
+        assert_eq!(vec![(1, "a.b"), (4, "A.B")], anchored(true, true, true));
+    }
+
+    // Tests that `-x` selects only the lines the query matches whole, case-folded with `-i`, in both search
+    // modes, unlike `-w` which also selects a line containing the query as a word.
+    #[test]
+    fn line_regexp() {
+        let contents = "\
+fox
+the fox jumps
+FOX
+foxes";
+        let query = |query: &str| {
+            Config::new(query.to_string(), InputType::LiteralInput(String::new()))
+                .with_line_regexp(true)
+        };
+        let whole_lines =
+            |config: Config| search(&Matcher::from_config(&config).unwrap(), false, contents);
+
+        assert_eq!(vec![(1, "fox")], whole_lines(query("fox")));
+        assert_eq!(
+            vec![(1, "fox"), (3, "FOX")],
+            whole_lines(query("fox").with_ignore_case(true))
+        );
+        assert_eq!(
+            vec![(1, "fox"), (2, "the fox jumps"), (3, "FOX")],
+            whole_lines(
+                query("fox")
+                    .with_line_regexp(false)
+                    .with_word(true)
+                    .with_ignore_case(true)
+            )
+        );
+        assert_eq!(
+            vec![(1, "fox"), (4, "foxes")],
+            whole_lines(query("fox(es)?").with_regex(true))
+        );
+        assert!(whole_lines(query("fox jumps")).is_empty());
+    }
+
+    // Tests that `-U` selects every line of a two-line match, with its parts highlighted and its context, that
+    // `--multiline-dotall` lets `.` cross lines, and that `-o` prints the whole match.
+    #[test]
+    fn multiline_matches() {
+        let input = "fn main() {\n    run();\n}\nfn run() {}\nlast\n";
+        let print = |args: &[&str]| {
+            let mut args = args.to_vec();
+            args.push("Cargo.toml");
+            // Only the matches are colored, so the prefixes are plain.
+            let config = Config::build_from_args(&args)
+                .unwrap()
+                .with_prefix_colors(PrefixColors::parse("fn=:ln=:bn=:se="));
+            render_with(&config, None, input)
+        };
+
+        assert_eq!(
+            (
+                2,
+                "1:fn \x1b[31mmain() {\x1b[0m\n2:\x1b[31m    run\x1b[0m();\n3-}\n".to_string()
+            ),
+            print(&[
+                "-U",
+                "-e",
+                r"main\(\) \{\n\s*\w+",
+                "-n",
+                "-A",
+                "1",
+                "--color=always"
+            ])
+        );
+        assert_eq!(
+            (0, String::new()),
+            print(&["-U", "-e", r"main.*?run", "-n"])
+        );
+        assert_eq!(
+            (2, "1:fn main() {\n2:    run();\n".to_string()),
+            print(&["-U", "-e", r"main.*?run", "-n", "--multiline-dotall"])
+        );
+        assert_eq!(
+            (2, "\x1b[31m}\nfn\x1b[0m\n".to_string()),
+            print(&["-U", "-e", r"\}\nfn", "-o", "--color=always"])
+        );
+        assert_eq!(
+            (2, "2\n".to_string()),
+            print(&["-U", "-e", r"fn.*\n.*run", "-c"])
+        );
+        assert!(Config::build_from_args(&["fox", "--multiline-dotall", "Cargo.toml"]).is_err());
+    }
+
+    // Tests that `--engine=auto` makes a query with a metacharacter a regex and leaves a plain one literal, that
+    // `--engine=literal` matches it literally, even with `-e`, and that `-F` wins over `--engine=regex`.
+    #[test]
+    fn engines() {
+        let contents = "a.b\naxb";
+        let selected = |args: &[&str]| {
+            let mut args = args.to_vec();
+            args.push("Cargo.toml");
+            let config = Config::build_from_args(&args).unwrap();
+            search(&Matcher::from_config(&config).unwrap(), false, contents)
+        };
+
+        assert_eq!(
+            vec![(1, "a.b"), (2, "axb")],
+            selected(&["a.b", "--engine=auto"])
+        );
+        assert_eq!(vec![(1, "a.b")], selected(&["a.b", "--engine=literal"]));
+        assert_eq!(
+            vec![(1, "a.b")],
+            selected(&["a.b", "--engine=literal", "-e"])
+        );
+        assert_eq!(vec![(1, "a.b")], selected(&["a.b", "--engine=regex", "-F"]));
+        assert_eq!(
+            vec![(1, "a.b"), (2, "axb")],
+            selected(&["--engine", "regex", "a.b"])
+        );
+        assert_eq!(
+            vec![(1, "a.b")],
+            selected(&["--engine", "literal", "a.b", "-e"])
+        );
+        assert_eq!(vec![(1, "a.b")], selected(&["a.b"]));
+
+        assert!(matches!(
+            Config::build_from_args(&["fox", "--engine=pcre"]),
+            Err(MgrepError::InvalidValue { flag, .. }) if flag == "--engine"
+        ));
+    }
 
-        // Checks if the configuration indicates that comparison should be case-insensitive.
-        assert_eq!(true, config.ignore_case);
+    // Tests that combined short flags are split like grep does, that the rest of a cluster after a flag taking a
+    // value is its value, and that an unknown letter is reported.
+    #[test]
+    fn short_flag_clusters() {
+        let config = Config::build_from_args(&["fox", "-in", "Cargo.toml"]).unwrap();
+        assert!(config.ignore_case && config.line_number);
+
+        let config = Config::build_from_args(&["fox", "-vc", "Cargo.toml"]).unwrap();
+        assert!(config.invert && config.count);
+
+        let config = Config::build_from_args(&["fox", "-wm", "2", "Cargo.toml"]).unwrap();
+        assert!(config.word);
+        assert_eq!(Some(2), config.max_count);
+
+        // `-ni` isn't `-n -i`, and a flag's value isn't split either.
+        let config = Config::build_from_args(&["fox", "-ni", "-R", "-ab", "Cargo.toml"]).unwrap();
+        assert!(!config.ignore_case && !config.line_number);
+        assert_eq!(Some("-ab"), config.replace.as_deref());
+
+        let err = Config::build_from_args(&["fox", "-iy", "Cargo.toml"])
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, MgrepError::Usage(message) if message.contains("invalid flag -y in"))
+        );
+
+        let config = Config::build_from_args(&["fox", "-A1", "Cargo.toml"]).unwrap();
+        assert_eq!(1, config.after);
+        let config = Config::build_from_args(&["fox", "-vm2", "Cargo.toml"]).unwrap();
+        assert!(config.invert);
+        assert_eq!(Some(2), config.max_count);
+        assert!(matches!(
+            Config::build_from_args(&["fox", "-mi", "2", "Cargo.toml"]),
+            Err(MgrepError::InvalidValue { flag, value, .. }) if flag == "--max-count" && value == "i"
+        ));
+    }
+
+    // Tests if the option to ignore case is applied when defined twice,
+    // once by the environment and once by the argument, and that `-ni` overrides the environment.
+    #[test]
+    fn double_opposite_case() {
+        // The environment variable ignores case (given to the build, as other tests read the process environment).
+        let build = |args: &[&str]| {
+            let args = std::iter::once("mgrep").chain(args.iter().copied());
+            Config::build_with_env(args.map(str::to_string), |name| {
+                (name == "IGNORE_CASE").then(|| OsString::from("1"))
+            })
+            .unwrap()
+        };
+
+        // Creates the configurations, with the option to ignore case, with the opposite one, and with neither
+        // (searching a file that exists, else stdin would be read).
+        assert!(build(&["to", "Cargo.toml"]).ignore_case);
+        assert!(build(&["to", "-i", "Cargo.toml"]).ignore_case);
+        assert!(!build(&["to", "-ni", "Cargo.toml"]).ignore_case);
+    }
+
+    // Tests that `IGNORE_CASE` ignores case unless it's empty, `0` or `false`.
+    #[test]
+    fn ignore_case_values() {
+        assert!(Config::get_ignore_case(&[], "to", Some("1")));
+        assert!(!Config::get_ignore_case(&[], "to", Some("false")));
+        assert!(!Config::get_ignore_case(&[], "to", Some("0")));
+        assert!(!Config::get_ignore_case(&[], "to", Some("")));
+        assert!(!Config::get_ignore_case(&[], "to", None));
     }
 }
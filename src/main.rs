@@ -16,16 +16,34 @@ use mgrep::Config;
 ///
 ///     File Path:
 ///         {ENVIRONMENT VARIABLE} cargo run -- ["QUERY"] [EXPRESSION] [PATH]
+///
+/// Exit status:
+///     0 if at least one line was selected, 1 if none was, 2 if an error stopped the search (like grep).
+///     Files that can't be read while searching a directory are reported, but don't change the status.
 fn main() {
     // Set up the command config based on the given arguments collected from CLI
     let config = Config::build(env::args()).unwrap_or_else(|err| {
         eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+        process::exit(2);
     });
 
     // Run the program based on the informations provided
-    if let Err(e) = mgrep::run(config) {
-        eprintln!("Application error: {e}");
+    let summary = mgrep::run(config).unwrap_or_else(|err| {
+        eprintln!("Application error: {err}");
+        process::exit(2);
+    });
+
+    // Files that couldn't be read are only reported, the exit status still depends on the matches.
+    for error in &summary.errors {
+        eprintln!("{error}");
+    }
+    match summary.errors.len() {
+        0 => {}
+        1 => eprintln!("mgrep: 1 file could not be read"),
+        failed => eprintln!("mgrep: {failed} files could not be read"),
+    }
+
+    if summary.count == 0 {
         process::exit(1);
     }
-}
\ No newline at end of file
+}
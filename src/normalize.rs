@@ -0,0 +1,104 @@
+/*
+    unicode_normalization: NFC and canonical composition
+*/
+use unicode_normalization::{
+    char::{canonical_combining_class, compose},
+    is_nfc, UnicodeNormalization,
+};
+
+/// A line in Unicode Normalization Form C (`--normalize`), so that a precomposed query (e.g. `é`, U+00E9) matches
+/// its decomposed form (`e` followed by U+0301) and the other way round.
+///
+/// The line is normalized segment by segment, a segment being a character and the ones that compose with it
+/// (e.g. its combining accents), so the matches found in the normalized text can be mapped back to the
+/// original line, where they're highlighted.
+pub(crate) struct Normalized {
+    pub(crate) text: String,
+    // Where each segment starts, in `text` and in the original line, followed by both ends.
+    starts: Vec<(usize, usize)>,
+}
+
+impl Normalized {
+    /// Normalizes `line`.
+    ///
+    /// # Returns
+    /// - `Some(Normalized)` if `line` isn't already in NFC.
+    /// - `None` otherwise, when it can be searched as it is.
+    pub(crate) fn new(line: &str) -> Option<Normalized> {
+        if is_nfc(line) {
+            return None;
+        }
+
+        let mut text = String::with_capacity(line.len());
+        let mut starts = Vec::new();
+        let mut segment = 0;
+        // The character the next ones may compose with (e.g. `e` and then U+0301 into `é`).
+        let mut last: Option<char> = None;
+
+        for (index, c) in line.char_indices() {
+            // A character starts a new segment unless it composes with the previous one (e.g. an accent, or the
+            // jamo of a Hangul syllable), or is a combining mark that doesn't (e.g. a second accent).
+            match (
+                last.and_then(|last| compose(last, c)),
+                canonical_combining_class(c),
+            ) {
+                (Some(composed), _) => last = Some(composed),
+                (None, 0) => {
+                    if index > 0 {
+                        starts.push((text.len(), segment));
+                        text.extend(line[segment..index].nfc());
+                        segment = index;
+                    }
+                    last = Some(c);
+                }
+                (None, _) => {}
+            }
+        }
+        starts.push((text.len(), segment));
+        text.extend(line[segment..].nfc());
+        starts.push((text.len(), line.len()));
+
+        Some(Normalized { text, starts })
+    }
+
+    /// Maps the byte span of a match in `text` to the original line, widened to whole segments (a match ending
+    /// inside an accented character highlights the whole character).
+    pub(crate) fn to_original(&self, (start, end): (usize, usize)) -> (usize, usize) {
+        // The last segment starting at or before `start`, and the first starting at or after `end`.
+        let first = self
+            .starts
+            .partition_point(|&(text_start, _)| text_start <= start)
+            - 1;
+        let after = self
+            .starts
+            .partition_point(|&(text_start, _)| text_start < end);
+
+        (self.starts[first].1, self.starts[after.max(first)].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests that decomposed characters are composed, and that the spans in the normalized text cover the
+    // same characters in the original line.
+    #[test]
+    fn normalized_spans() {
+        assert!(Normalized::new("café").is_none());
+
+        let line = "le cafe\u{301} noir";
+        let normalized = Normalized::new(line).unwrap();
+        assert_eq!("le café noir", normalized.text);
+
+        // `café` is at 3..8 in the normalized text, and at 3..9 in the line.
+        assert_eq!((3, 9), normalized.to_original((3, 8)));
+        assert_eq!("cafe\u{301}", &line[3..9]);
+        // `noir`, after the accent, is shifted by its extra byte.
+        assert_eq!((10, 14), normalized.to_original((9, 13)));
+
+        // The jamo of a Hangul syllable compose across what would otherwise be separate segments.
+        let normalized = Normalized::new("\u{1100}\u{1161}!").unwrap();
+        assert_eq!("\u{ac00}!", normalized.text);
+    }
+}
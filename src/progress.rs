@@ -0,0 +1,80 @@
+/*
+    sync: Counters shared by the search threads;
+    time: Throttled reports
+*/
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+// The minimum time between two reports, so the terminal is updated about 10 times per second.
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Counts the files searched (and their matches) by a recursive search, for `--progress`.
+///
+/// The counters are updated from every search thread, and only one report is produced per `INTERVAL`.
+pub(crate) struct Progress {
+    start: Instant,
+    files: AtomicUsize,
+    matches: AtomicUsize,
+    // Milliseconds from `start` to the last report.
+    reported_at: AtomicU64,
+}
+
+impl Progress {
+    pub(crate) fn new(start: Instant) -> Progress {
+        Progress {
+            start,
+            files: AtomicUsize::new(0),
+            matches: AtomicUsize::new(0),
+            reported_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts one more searched file, with its number of matches.
+    ///
+    /// # Returns
+    /// - `Some(report)`: A line like `searched 1423 files, 57 matches`, if the last report is at least
+    ///   `INTERVAL` old at `now`.
+    /// - `None`: Otherwise, including when another thread just reported.
+    pub(crate) fn record(&self, matches: usize, now: Instant) -> Option<String> {
+        let files = self.files.fetch_add(1, Ordering::Relaxed) + 1;
+        let matches = self.matches.fetch_add(matches, Ordering::Relaxed) + matches;
+
+        let elapsed = now.saturating_duration_since(self.start).as_millis() as u64;
+        let reported_at = self.reported_at.load(Ordering::Relaxed);
+        if elapsed < reported_at + INTERVAL.as_millis() as u64 {
+            return None;
+        }
+
+        // Only the thread that moves the report time forward prints.
+        self.reported_at
+            .compare_exchange(reported_at, elapsed, Ordering::Relaxed, Ordering::Relaxed)
+            .ok()
+            .map(|_| format!("searched {files} files, {matches} matches"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests that the counters add up, and that reports are throttled to one per interval.
+    #[test]
+    fn throttled_reports() {
+        let start = Instant::now();
+        let progress = Progress::new(start);
+
+        assert_eq!(None, progress.record(2, start));
+        assert_eq!(None, progress.record(0, start + INTERVAL / 2));
+        assert_eq!(
+            Some("searched 3 files, 5 matches".to_string()),
+            progress.record(3, start + INTERVAL)
+        );
+        assert_eq!(None, progress.record(1, start + INTERVAL + INTERVAL / 2));
+        assert_eq!(
+            Some("searched 5 files, 7 matches".to_string()),
+            progress.record(1, start + INTERVAL * 2)
+        );
+    }
+}
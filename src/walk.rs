@@ -0,0 +1,424 @@
+/*
+    collections: Visited directory bookkeeping;
+    env: Debug notices;
+    fs: Directory traversal;
+    io: Directory identities;
+    path: Path manipulation;
+    glob: File name filters;
+    ignore: Ignore files
+*/
+use std::{
+    collections::HashSet,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{glob, ignore::Rules};
+
+/// What a walk descends into, and which of the files it finds are kept. The default keeps everything but
+/// hidden entries and symlinked directories, at any depth.
+#[derive(Default)]
+pub(crate) struct Filters<'a> {
+    /// Glob patterns (`--include`); when not empty, only the files whose name matches one are kept.
+    pub(crate) include: &'a [String],
+    /// Glob patterns (`--exclude`); the files whose name matches one are left out, even if included.
+    pub(crate) exclude: &'a [String],
+    /// The size in bytes (`--max-filesize`) above which files are left out.
+    pub(crate) max_filesize: Option<u64>,
+    /// How many levels of subdirectories are descended into (`--max-depth`); with `Some(0)` only the files
+    /// directly in the root are collected.
+    pub(crate) max_depth: Option<usize>,
+    /// Whether the `.gitignore` and `.mgrepignore` files met on the way leave the files and directories they
+    /// match out (see `ignore::Rules`), unlike with `--no-ignore`. Ignored directories aren't even read.
+    pub(crate) ignore: bool,
+    /// Whether symlinks to directories are descended into (`--follow`); they're skipped otherwise. The root
+    /// itself is walked even if it's a symlink.
+    pub(crate) follow: bool,
+    /// Whether the hidden files and directories (whose name starts with `.`) are walked too (`--hidden`);
+    /// they're skipped otherwise, the root excepted. Ignore files are read either way.
+    pub(crate) hidden: bool,
+    /// Whether the directories that can't be read are skipped silently (`-s`), instead of with a warning.
+    pub(crate) no_messages: bool,
+}
+
+/// Collects every regular file underneath `root`, descending into subdirectories, as the `filters` allow.
+///
+/// # Returns
+/// The paths of all regular files found and kept, in the order they were discovered.
+///
+/// # Note
+/// - Directories that cannot be read are skipped with a warning on stderr (unless `no_messages` is set) instead
+///   of aborting the walk.
+/// - Each directory is visited at most once (by its device and inode), so symlink loops can't recurse forever.
+/// - Files left out for their size are only reported on stderr when the `MGREP_DEBUG` environment variable is set.
+pub(crate) fn collect_files(root: &Path, filters: &Filters) -> Vec<PathBuf> {
+    let Filters {
+        include,
+        exclude,
+        max_filesize,
+        ..
+    } = *filters;
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut rules = Rules::default();
+
+    visit(
+        root,
+        filters.max_depth,
+        filters.ignore.then_some(&mut rules),
+        filters,
+        &mut visited,
+        &mut files,
+    );
+
+    // Only the file names are matched, so `*.rs` applies at any depth.
+    files.retain(|file| {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+
+        (include.is_empty() || include.iter().any(|pattern| glob::matches(pattern, &name)))
+            && !exclude.iter().any(|pattern| glob::matches(pattern, &name))
+    });
+
+    // Only the files left are stat'ed. A file whose size can't be read is kept, so opening it reports the error.
+    if let Some(max_filesize) = max_filesize {
+        let debug = env::var_os("MGREP_DEBUG").is_some();
+
+        files.retain(|file| match fs::metadata(file) {
+            Ok(metadata) if metadata.len() > max_filesize => {
+                if debug {
+                    eprintln!(
+                        "mgrep: {}: skipped, larger than --max-filesize ({} bytes)",
+                        file.display(),
+                        metadata.len()
+                    );
+                }
+                false
+            }
+            _ => true,
+        });
+    }
+
+    files
+}
+
+// Walks a single directory, pushing its files and recursing into its subdirectories, as long as `depth`
+// (the levels still allowed below `dir`, `None` for no limit) isn't exhausted. The entries matched by the
+// ignore `rules` (if enabled) are skipped, and so are the hidden entries and the symlinks to directories,
+// unless the `filters` keep them.
+fn visit(
+    dir: &Path,
+    depth: Option<usize>,
+    mut rules: Option<&mut Rules>,
+    filters: &Filters,
+    visited: &mut HashSet<DirId>,
+    files: &mut Vec<PathBuf>,
+) {
+    // A directory reached twice (e.g. through a symlink pointing back up the tree) is a loop.
+    match dir_id(dir) {
+        Ok(id) => {
+            if !visited.insert(id) {
+                return;
+            }
+        }
+        Err(err) => {
+            warn(filters, dir, &err);
+            return;
+        }
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn(filters, dir, &err);
+            return;
+        }
+    };
+
+    // The ignore files of `dir` apply to everything under it, and are dropped once it's walked.
+    let len = rules.as_deref_mut().map(|rules| rules.enter(dir));
+
+    for entry in entries {
+        let (path, is_symlink) = match entry {
+            Ok(entry) => (
+                entry.path(),
+                entry.file_type().is_ok_and(|kind| kind.is_symlink()),
+            ),
+            Err(err) => {
+                warn(filters, dir, &err);
+                continue;
+            }
+        };
+
+        let is_hidden = path
+            .file_name()
+            .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."));
+        if is_hidden && !filters.hidden {
+            continue;
+        }
+
+        // `is_dir`/`is_file` follow symlinks: linked files are always searched, linked directories only
+        // walked with `--follow`.
+        let is_dir = path.is_dir();
+        if is_dir && is_symlink && !filters.follow {
+            continue;
+        }
+        if rules
+            .as_deref()
+            .is_some_and(|rules| rules.is_ignored(&path, is_dir))
+        {
+            continue;
+        }
+
+        if is_dir {
+            // At the last level, subdirectories are left out without even being read.
+            if depth != Some(0) {
+                visit(
+                    &path,
+                    depth.map(|depth| depth - 1),
+                    rules.as_deref_mut(),
+                    filters,
+                    visited,
+                    files,
+                );
+            }
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+
+    if let (Some(rules), Some(len)) = (rules, len) {
+        rules.leave(len);
+    }
+}
+
+// Reports that `dir` can't be walked, unless `-s` silences it.
+fn warn(filters: &Filters, dir: &Path, err: &io::Error) {
+    if !filters.no_messages {
+        eprintln!("mgrep: {}: {err}", dir.display());
+    }
+}
+
+// What identifies a directory however it's reached: its device and inode number on Unix, else its canonical
+// path.
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(dir: &Path) -> io::Result<DirId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(dir)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(dir: &Path) -> io::Result<DirId> {
+    fs::canonicalize(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A scratch directory named after `name` under the temp dir, holding the given `(path, contents)` files, and
+    // removed once dropped.
+    struct Tree {
+        root: PathBuf,
+    }
+
+    impl Tree {
+        fn new(name: &str, files: &[(&str, &str)]) -> Tree {
+            let root = env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            for (path, contents) in files {
+                let path = root.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+            Tree { root }
+        }
+
+        // The files collected with `filters`, relative to the root, and sorted.
+        fn collect(&self, filters: &Filters) -> Vec<String> {
+            let mut files: Vec<String> = collect_files(&self.root, filters)
+                .iter()
+                .map(|path| {
+                    let path = path.strip_prefix(&self.root).unwrap();
+                    path.to_string_lossy().into_owned()
+                })
+                .collect();
+            files.sort();
+            files
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    // Tests that files in nested directories are all collected.
+    #[test]
+    fn collects_nested_files() {
+        let tree = Tree::new(
+            "mgrep-walk",
+            &[("top.txt", "top"), ("a/b/deep.txt", "deep")],
+        );
+
+        assert_eq!(
+            vec!["a/b/deep.txt", "top.txt"],
+            tree.collect(&Filters::default())
+        );
+    }
+
+    // Tests that only included files are collected, and that excluding takes precedence.
+    #[test]
+    fn include_and_exclude() {
+        let tree = Tree::new(
+            "mgrep-walk-filter",
+            &[
+                ("src/lib.rs", "lib"),
+                ("src/lib_test.rs", "test"),
+                ("README.md", "readme"),
+            ],
+        );
+        let include = vec!["*.rs".to_string()];
+        let exclude = vec!["*_test.rs".to_string()];
+
+        assert_eq!(
+            vec!["src/lib.rs"],
+            tree.collect(&Filters {
+                include: &include,
+                exclude: &exclude,
+                ..Filters::default()
+            })
+        );
+    }
+
+    // Tests that the files larger than the maximum size are left out, and the others kept.
+    #[test]
+    fn max_filesize() {
+        let large = "large".repeat(100);
+        let tree = Tree::new(
+            "mgrep-walk-size",
+            &[("small.txt", "small"), ("large.txt", &large)],
+        );
+
+        assert_eq!(
+            vec!["small.txt"],
+            tree.collect(&Filters {
+                max_filesize: Some(100),
+                ..Filters::default()
+            })
+        );
+    }
+
+    // Tests that the files below the maximum depth are left out, and those above it kept.
+    #[test]
+    fn max_depth() {
+        let tree = Tree::new(
+            "mgrep-walk-depth",
+            &[
+                ("top.txt", "top"),
+                ("a/middle.txt", "middle"),
+                ("a/b/deep.txt", "deep"),
+            ],
+        );
+        let collect = |max_depth| {
+            tree.collect(&Filters {
+                max_depth: Some(max_depth),
+                ..Filters::default()
+            })
+        };
+
+        assert_eq!(vec!["top.txt"], collect(0));
+        assert_eq!(vec!["a/middle.txt", "top.txt"], collect(1));
+        assert_eq!(3, collect(2).len());
+    }
+
+    // Tests that a symlinked directory is only walked with `follow`, and that a symlink back up the tree
+    // doesn't make the walk loop forever.
+    #[cfg(unix)]
+    #[test]
+    fn followed_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let tree = Tree::new("mgrep-walk-follow", &[("a/own.txt", "own")]);
+        let outside = Tree::new("mgrep-walk-outside", &[("linked.txt", "linked")]);
+        symlink(&outside.root, tree.root.join("link")).unwrap();
+        symlink(&tree.root, tree.root.join("a/loop")).unwrap();
+        let collect = |follow| {
+            tree.collect(&Filters {
+                follow,
+                ..Filters::default()
+            })
+        };
+
+        assert_eq!(vec!["a/own.txt"], collect(false));
+        assert_eq!(vec!["a/own.txt", "link/linked.txt"], collect(true));
+    }
+
+    // Tests that the hidden files and directories are skipped unless asked for, and that a hidden root is still
+    // walked.
+    #[test]
+    fn hidden_entries() {
+        let tree = Tree::new(
+            ".mgrep-walk-hidden",
+            &[
+                ("visible.txt", "visible"),
+                (".hidden", "hidden"),
+                (".config/settings.toml", "settings"),
+            ],
+        );
+        let collect = |hidden| {
+            tree.collect(&Filters {
+                hidden,
+                ..Filters::default()
+            })
+        };
+
+        assert_eq!(vec!["visible.txt"], collect(false));
+        assert_eq!(
+            vec![".config/settings.toml", ".hidden", "visible.txt"],
+            collect(true)
+        );
+    }
+
+    // Tests that the files and directories matched by `.gitignore` files are skipped, that a nested one only
+    // applies to its own subtree, and that nothing is skipped without the ignore rules.
+    #[test]
+    fn ignore_files() {
+        let tree = Tree::new(
+            "mgrep-walk-ignore",
+            &[
+                (".gitignore", "target/\n*.log\n"),
+                ("src/.gitignore", "generated/\n"),
+                ("target/debug/mgrep.d", "build"),
+                ("src/generated/parser.rs", "generated"),
+                ("src/main.rs", "main"),
+                ("src/debug.log", "log"),
+                ("docs/generated", "docs"),
+            ],
+        );
+        let collect = |ignore| {
+            tree.collect(&Filters {
+                ignore,
+                hidden: true,
+                ..Filters::default()
+            })
+        };
+
+        assert_eq!(
+            vec![
+                ".gitignore",
+                "docs/generated",
+                "src/.gitignore",
+                "src/main.rs"
+            ],
+            collect(true)
+        );
+        assert_eq!(7, collect(false).len());
+    }
+}
@@ -0,0 +1,1084 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+// Runs the compiled binary with the given arguments.
+fn mgrep(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mgrep"))
+        .args(args)
+        .output()
+        .expect("failed to run mgrep")
+}
+
+// Runs the compiled binary with the given arguments, feeding `input` through stdin.
+fn mgrep_stdin(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mgrep"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run mgrep");
+
+    // mgrep may exit before reading stdin (e.g. on an invalid argument), closing the pipe early.
+    if let Err(err) = child.stdin.take().unwrap().write_all(input.as_bytes()) {
+        assert_eq!(std::io::ErrorKind::BrokenPipe, err.kind());
+    }
+
+    child.wait_with_output().expect("failed to run mgrep")
+}
+
+// Tests that `-h` prints the help message and exits successfully instead of being searched for.
+#[test]
+fn short_help() {
+    let output = mgrep(&["-h"]);
+
+    assert_eq!(Some(0), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage:"));
+}
+
+// Tests that `--help` behaves the same as `-h`.
+#[test]
+fn long_help() {
+    let output = mgrep(&["--help"]);
+
+    assert_eq!(Some(0), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage:"));
+}
+
+// Tests that overlapping context windows are merged and distinct groups are separated by `--`, and that
+// context lines are told apart from matches by a `-` after the line number (colored like grep).
+#[test]
+fn context_groups() {
+    let input = "match\nb\nc\nd\nmatch\ne\nmatch\nf\n";
+    let output = mgrep_stdin(&["match", "-C", "1", "-n", "--color=always"], input);
+
+    assert_eq!(
+        "\x1b[32m1\x1b[0m\x1b[36m:\x1b[0m\x1b[31mmatch\x1b[0m\n\x1b[32m2\x1b[0m\x1b[36m-\x1b[0mb\n--\n\
+        \x1b[32m4\x1b[0m\x1b[36m-\x1b[0md\n\x1b[32m5\x1b[0m\x1b[36m:\x1b[0m\x1b[31mmatch\x1b[0m\n\x1b[32m6\x1b[0m\x1b[36m-\x1b[0me\n\
+        \x1b[32m7\x1b[0m\x1b[36m:\x1b[0m\x1b[31mmatch\x1b[0m\n\x1b[32m8\x1b[0m\x1b[36m-\x1b[0mf\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+// Tests that `--group-separator` replaces the `--` between groups (and only between them), and that
+// `--no-group-separator` removes it.
+#[test]
+fn custom_group_separator() {
+    let input = "match\nb\nc\nd\nmatch\n";
+    let custom = mgrep_stdin(
+        &["match", "-A", "1", "--group-separator=~~", "--color=never"],
+        input,
+    );
+    let none = mgrep_stdin(
+        &["match", "-A", "1", "--no-group-separator", "--color=never"],
+        input,
+    );
+
+    assert_eq!(
+        "match\nb\n~~\nmatch\n",
+        String::from_utf8_lossy(&custom.stdout)
+    );
+    assert_eq!("match\nb\nmatch\n", String::from_utf8_lossy(&none.stdout));
+}
+
+// Creates an empty scratch directory for a test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mgrep-cli-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Tests that a parallel recursive search prints every file's matches in a stable order, and that an
+// unreadable file is reported without hiding the matches of the others.
+#[test]
+fn parallel_recursive_search() {
+    let dir = temp_dir("parallel");
+    for index in 0..20 {
+        fs::write(
+            dir.join(format!("{index:02}.txt")),
+            format!("needle {index}\n"),
+        )
+        .unwrap();
+    }
+    // The invalid UTF-8 comes after the first chunk, so the file isn't detected as binary.
+    let invalid = ["hay\n".repeat(5000).as_bytes(), b"needle \xff\n"].concat();
+    fs::write(dir.join("invalid.txt"), invalid).unwrap();
+
+    let output = mgrep(&[
+        "needle",
+        dir.to_str().unwrap(),
+        "-r",
+        "-j",
+        "4",
+        "--color=never",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(20, stdout.lines().count());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid.txt"));
+    assert!(stderr.contains("1 file could not be read"));
+    assert_eq!(Some(0), output.status.code());
+
+    // Same files, same order, whatever the number of threads.
+    let sequential = mgrep(&[
+        "needle",
+        dir.to_str().unwrap(),
+        "-r",
+        "-j",
+        "1",
+        "--color=never",
+    ]);
+    assert_eq!(stdout, String::from_utf8_lossy(&sequential.stdout));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `-m` caps the count and stops reading the file, so an invalid line after the limit is never read.
+#[test]
+fn max_count_stops_early() {
+    let dir = temp_dir("max-count");
+    let file = dir.join("input.txt");
+    // The invalid UTF-8 comes after the first chunk, so the file isn't detected as binary.
+    let contents = [
+        b"match 1\nmatch 2\nother\nmatch 3\n".as_slice(),
+        "padding\n".repeat(5000).as_bytes(),
+        b"\xff\n",
+    ]
+    .concat();
+    fs::write(&file, contents).unwrap();
+
+    let output = mgrep(&["match", file.to_str().unwrap(), "-m", "2", "-c"]);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("2\n", String::from_utf8_lossy(&output.stdout));
+
+    // The trailing context of the last match is still printed.
+    let output = mgrep(&[
+        "match",
+        file.to_str().unwrap(),
+        "--max-count=2",
+        "-A",
+        "1",
+        "--color=never",
+    ]);
+    assert_eq!(
+        "match 1\nmatch 2\nother\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--max-total` prints exactly N matching lines over several files, the first ones in the order the
+// files are printed, whatever the number of threads.
+#[test]
+fn max_total_across_files() {
+    let dir = temp_dir("max_total_across_files");
+    for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+        fs::write(
+            dir.join(name),
+            format!("fox 1 in {name}\nno\nfox 2 in {name}\n"),
+        )
+        .unwrap();
+    }
+    let dir_arg = dir.to_str().unwrap();
+
+    for threads in ["1", "4"] {
+        let output = mgrep(&[
+            "fox",
+            dir_arg,
+            "-r",
+            "--sort=path",
+            "--no-filename",
+            "-j",
+            threads,
+            "--max-total=3",
+        ]);
+        assert_eq!(Some(0), output.status.code());
+        assert_eq!(
+            "fox 1 in a.txt\nfox 2 in a.txt\nfox 1 in b.txt\n",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+
+    // The file where the total is reached isn't read (nor preprocessed) a second time.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let scripts = temp_dir("max_total_pre");
+        let runs = scripts.join("runs.log");
+        let pre = scripts.join("log.sh");
+        fs::write(
+            &pre,
+            format!("#!/bin/sh\necho \"$1\" >> {}\ncat\n", runs.display()),
+        )
+        .unwrap();
+        fs::set_permissions(&pre, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let args = [
+            dir_arg,
+            "-r",
+            "--sort=path",
+            "--no-filename",
+            "--max-total=3",
+        ];
+        let output = mgrep(&[&["fox", "--pre", pre.to_str().unwrap()][..], &args].concat());
+        assert_eq!(
+            "fox 1 in a.txt\nfox 2 in a.txt\nfox 1 in b.txt\n",
+            String::from_utf8_lossy(&output.stdout)
+        );
+        let runs = fs::read_to_string(&runs).unwrap();
+        assert_eq!(1, runs.lines().filter(|run| run.ends_with("b.txt")).count());
+
+        fs::remove_dir_all(&scripts).unwrap();
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `-l` and `-L` list complementary sets of files, and can't be combined.
+#[test]
+fn files_with_and_without_matches() {
+    let dir = temp_dir("files-with-matches");
+    fs::write(dir.join("a.txt"), "needle\nneedle\n").unwrap();
+    fs::write(dir.join("b.txt"), "hay\n").unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let output = mgrep(&["needle", dir_arg, "-r", "-l"]);
+    assert_eq!(
+        format!("{}\n", dir.join("a.txt").display()),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = mgrep(&["needle", dir_arg, "-r", "-L"]);
+    assert_eq!(
+        format!("{}\n", dir.join("b.txt").display()),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = mgrep(&["needle", dir_arg, "-r", "-l", "-L"]);
+    assert_ne!(Some(0), output.status.code());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that quiet mode prints nothing and only reports the match through the exit status.
+#[test]
+fn quiet_exit_status() {
+    let output = mgrep_stdin(&["fox", "-q"], "The quick brown fox\n");
+    assert!(output.stdout.is_empty());
+    assert_eq!(Some(0), output.status.code());
+
+    let output = mgrep_stdin(&["cat", "--quiet"], "The quick brown fox\n");
+    assert!(output.stdout.is_empty());
+    assert_eq!(Some(1), output.status.code());
+}
+
+// Tests the grep-style exit statuses: 0 on a match, 1 without any, 2 on an error.
+#[test]
+fn exit_statuses() {
+    let output = mgrep_stdin(&["fox"], "The quick brown fox\n");
+    assert_eq!(Some(0), output.status.code());
+
+    let output = mgrep_stdin(&["cat"], "The quick brown fox\n");
+    assert_eq!(Some(1), output.status.code());
+
+    let output = mgrep_stdin(&["(", "--regex"], "The quick brown fox\n");
+    assert_eq!(Some(2), output.status.code());
+
+    let output = mgrep_stdin(&["fox", "-m", "many"], "The quick brown fox\n");
+    assert_eq!(Some(2), output.status.code());
+}
+
+// Tests that only the matched parts are printed, once per match.
+#[test]
+fn only_matching() {
+    let output = mgrep_stdin(
+        &[r"\d+", "--regex", "-o", "-n"],
+        "no digits\nport 80 and 443\n",
+    );
+    assert_eq!("2:80\n2:443\n", String::from_utf8_lossy(&output.stdout));
+}
+
+// Tests that `-c` with `-o` counts every occurrence instead of the lines, like `--count-matches`.
+#[test]
+fn count_only_matching() {
+    let input = "fox and fox and fox\nno match\n";
+
+    for args in [&["fox", "-c", "-o"][..], &["fox", "-oc"]] {
+        let output = mgrep_stdin(args, input);
+        assert_eq!(Some(0), output.status.code());
+        assert_eq!("3\n", String::from_utf8_lossy(&output.stdout));
+    }
+}
+
+// Tests that a query or argument containing a slash still searches stdin, unless it names an existing path.
+#[test]
+fn slash_query_searches_stdin() {
+    let output = mgrep_stdin(&["a/b", "not/a/file.txt"], "x a/b y\nnothing\n");
+    assert_eq!("x a/b y\n", String::from_utf8_lossy(&output.stdout));
+
+    // `-` and `--stdin` win over an existing path.
+    let output = mgrep_stdin(&["a/b", "src", "-"], "x a/b y\n");
+    assert_eq!("x a/b y\n", String::from_utf8_lossy(&output.stdout));
+
+    let output = mgrep_stdin(&["a/b", "src", "--stdin"], "x a/b y\n");
+    assert_eq!("x a/b y\n", String::from_utf8_lossy(&output.stdout));
+}
+
+// Tests that `-Z` terminates the filenames with NUL bytes, both with `-l` and in front of the lines.
+#[test]
+fn null_separated_filenames() {
+    let dir = temp_dir("null");
+    fs::write(dir.join("a.txt"), "needle\n").unwrap();
+    let file = dir.join("a.txt").display().to_string();
+
+    let output = mgrep(&["needle", dir.to_str().unwrap(), "-r", "-l", "-Z"]);
+    assert_eq!(format!("{file}\0"), String::from_utf8_lossy(&output.stdout));
+
+    let output = mgrep(&[
+        "needle",
+        dir.to_str().unwrap(),
+        "-r",
+        "--null",
+        "--color=never",
+    ]);
+    assert_eq!(
+        format!("{file}\0needle\n"),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--output` writes the results to a file, without highlighting, and that an uncreatable file is an error.
+#[test]
+fn output_file() {
+    let dir = temp_dir("output");
+    let file = dir.join("results.txt");
+
+    let output = mgrep_stdin(
+        &["fox", "--output", file.to_str().unwrap()],
+        "The quick brown fox\nlazy dog\n",
+    );
+    assert_eq!(Some(0), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert_eq!("The quick brown fox\n", fs::read_to_string(&file).unwrap());
+
+    let missing = dir.join("missing").join("results.txt");
+    let output = mgrep_stdin(
+        &["fox", &format!("--output={}", missing.display())],
+        "The quick brown fox\n",
+    );
+    assert_eq!(Some(2), output.status.code());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that the query is the first positional argument wherever the flags are, and that swapping it with
+// the path is reported instead of searching stdin for the path.
+#[test]
+fn swapped_query_and_path() {
+    let dir = temp_dir("swapped");
+    let file = dir.join("input.txt");
+    fs::write(&file, "fn main() {}\nlet x = 1;\n").unwrap();
+    let file = file.to_str().unwrap();
+
+    let output = mgrep(&["-n", "--color=never", "fn", file]);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("1:fn main() {}\n", String::from_utf8_lossy(&output.stdout));
+
+    let output = mgrep(&["-A", "1", "fn", file, "--color=never"]);
+    assert_eq!(
+        "fn main() {}\nlet x = 1;\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = mgrep(&[file, "fn"]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("the query comes first"));
+
+    // With `-` the path is knowingly searched for in stdin.
+    let output = mgrep_stdin(&[file, "fn", "-"], &format!("see {file}\n"));
+    assert_eq!(Some(0), output.status.code());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--heading` prints each file's name once above its lines, with blank lines between the groups.
+#[test]
+fn heading_groups() {
+    let dir = temp_dir("heading");
+    fs::write(dir.join("a.txt"), "needle 1\nhay\nneedle 2\n").unwrap();
+    fs::write(dir.join("b.txt"), "hay\n").unwrap();
+    fs::write(dir.join("c.txt"), "needle 3\n").unwrap();
+
+    let output = mgrep(&[
+        "needle",
+        dir.to_str().unwrap(),
+        "-r",
+        "-n",
+        "--heading",
+        "--color=never",
+        "-j",
+        "1",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.ends_with("\n") && !stdout.ends_with("\n\n"));
+    let mut groups: Vec<String> = stdout
+        .trim_end()
+        .split("\n\n")
+        .map(str::to_string)
+        .collect();
+    // The walk order isn't specified, only the layout of each group.
+    groups.sort();
+
+    assert_eq!(
+        vec![
+            format!("{}\n1:needle 1\n3:needle 2", dir.join("a.txt").display()),
+            format!("{}\n1:needle 3", dir.join("c.txt").display()),
+        ],
+        groups
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--separate-files` prints exactly one blank line between the matches of two files, none before
+// the first nor after the last, and that a file without a match adds none.
+#[test]
+fn separated_files() {
+    let dir = temp_dir("separated_files");
+    fs::write(dir.join("a.txt"), "needle 1\nhay\nneedle 2\n").unwrap();
+    fs::write(dir.join("b.txt"), "hay\n").unwrap();
+    fs::write(dir.join("c.txt"), "needle 3\n").unwrap();
+
+    let output = mgrep(&[
+        "needle",
+        dir.to_str().unwrap(),
+        "-r",
+        "--sort=path",
+        "--no-filename",
+        "--separate-files",
+    ]);
+    assert_eq!(
+        "needle 1\nneedle 2\n\nneedle 3\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that with `--line-buffered` a match is printed while stdin is still open, as with `tail -f`.
+#[test]
+fn line_buffered_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mgrep"))
+        .args(["error", "--line-buffered", "--color=never"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run mgrep");
+
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(b"info: started\nerror: disk full\n")
+        .unwrap();
+    stdin.flush().unwrap();
+
+    // The line is read on another thread, so a blocked mgrep fails the test instead of hanging it.
+    let stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        BufReader::new(stdout).read_line(&mut line).unwrap();
+        sender.send(line).unwrap();
+    });
+
+    let line = receiver.recv_timeout(Duration::from_secs(10));
+    drop(stdin);
+    let status = child.wait().unwrap();
+
+    assert_eq!(Ok("error: disk full\n".to_string()), line);
+    assert_eq!(Some(0), status.code());
+}
+
+// Tests that `--sort=path` prints the files of a tree in lexicographic order, identically on every run.
+#[test]
+fn sorted_recursive_search() {
+    let dir = temp_dir("sort");
+    for name in ["b.txt", "a/z.txt", "c/a.txt", "a.txt", "a/b/c.txt"] {
+        let file = dir.join(name);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(file, "needle\n").unwrap();
+    }
+
+    let run = || {
+        let output = mgrep(&["needle", dir.to_str().unwrap(), "-r", "-l", "--sort=path"]);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let first = run();
+
+    // Paths are compared component by component, so `a/...` comes before `a.txt`.
+    let expected: Vec<String> = ["a/b/c.txt", "a/z.txt", "a.txt", "b.txt", "c/a.txt"]
+        .iter()
+        .map(|name| format!("{}\n", dir.join(name).display()))
+        .collect();
+    assert_eq!(expected.concat(), first);
+    assert_eq!(first, run());
+
+    let output = mgrep(&["needle", dir.to_str().unwrap(), "-r", "--sort=size"]);
+    assert_eq!(Some(2), output.status.code());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--files` lists the files a recursive search would read, filters included, without a query.
+#[test]
+fn list_files() {
+    let dir = temp_dir("files");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("src/lib.rs"), "lib").unwrap();
+    fs::write(dir.join("src/lib_test.rs"), "test").unwrap();
+    fs::write(dir.join("README.md"), "readme").unwrap();
+
+    let output = mgrep(&[
+        "--files",
+        dir.to_str().unwrap(),
+        "--include=*.rs",
+        "--exclude=*_test.rs",
+    ]);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(
+        format!("{}\n", dir.join("src/lib.rs").display()),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = mgrep(&["--files", dir.to_str().unwrap(), "--sort=path"]);
+    let expected: Vec<String> = ["README.md", "src/lib.rs", "src/lib_test.rs"]
+        .iter()
+        .map(|name| format!("{}\n", dir.join(name).display()))
+        .collect();
+    assert_eq!(expected.concat(), String::from_utf8_lossy(&output.stdout));
+
+    let output = mgrep(&["--files"]);
+    assert_eq!(Some(2), output.status.code());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--progress` leaves the results on stdout unchanged, and is silent when stderr isn't a terminal.
+#[test]
+fn progress_is_silent_when_redirected() {
+    let dir = temp_dir("progress");
+    for index in 0..50 {
+        fs::write(dir.join(format!("{index:02}.txt")), "needle\nhay\n").unwrap();
+    }
+    let dir_arg = dir.to_str().unwrap();
+
+    let plain = mgrep(&["needle", dir_arg, "-r", "--sort=path", "--color=never"]);
+    let output = mgrep(&[
+        "needle",
+        dir_arg,
+        "-r",
+        "--sort=path",
+        "--color=never",
+        "--progress",
+    ]);
+
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(plain.stdout, output.stdout);
+    assert!(output.stderr.is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that the value of `IGNORE_CASE` is parsed, instead of only checking that it's set.
+#[test]
+fn ignore_case_env_values() {
+    let matches = |value: &str, args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_mgrep"))
+            .args(args)
+            .env("IGNORE_CASE", value)
+            .stdin(Stdio::null())
+            .output()
+            .expect("failed to run mgrep");
+        output.status.code() == Some(0)
+    };
+    let dir = temp_dir("ignore_case_env");
+    let file = dir.join("input.txt");
+    fs::write(&file, "Needle\n").unwrap();
+    let args = ["needle", file.to_str().unwrap()];
+
+    assert!(!matches("0", &args));
+    assert!(!matches("false", &args));
+    assert!(!matches("", &args));
+    assert!(matches("1", &args));
+    assert!(matches("true", &args));
+    assert!(!matches("1", &[args[0], args[1], "-ni"]));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--encoding` transcodes a Latin-1 file, that UTF-16 is detected from its byte order mark,
+// and that an unknown encoding is reported.
+#[test]
+fn encodings() {
+    let dir = temp_dir("encodings");
+    let latin1 = dir.join("latin1.txt");
+    fs::write(&latin1, b"caf\xe9 cr\xe8me\nth\xe9\n").unwrap();
+    let utf16 = dir.join("utf16.txt");
+    let mut bytes = vec![0xff, 0xfe];
+    bytes.extend("crème brûlée\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+    fs::write(&utf16, bytes).unwrap();
+
+    let output = mgrep(&["crème", latin1.to_str().unwrap(), "--encoding=latin1"]);
+    assert_eq!("café crème\n", String::from_utf8_lossy(&output.stdout));
+
+    let output = mgrep(&["brûlée", utf16.to_str().unwrap(), "--color=never"]);
+    assert_eq!("crème brûlée\n", String::from_utf8_lossy(&output.stdout));
+
+    let output = mgrep(&["crème", latin1.to_str().unwrap(), "--encoding=klingon"]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown encoding 'klingon'"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--total` prints the sum of the `-c` counts of every file, instead of one count per file.
+#[test]
+fn total_count() {
+    let dir = temp_dir("total_count");
+    fs::write(dir.join("a.txt"), "fox\nfox fox\nno\n").unwrap();
+    fs::write(dir.join("b.txt"), "no\nfox\n").unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let per_file = mgrep(&["fox", dir_arg, "-r", "-c", "--sort=path"]);
+    let total = mgrep(&["fox", dir_arg, "-r", "-c", "--total"]);
+
+    let a = dir.join("a.txt");
+    let b = dir.join("b.txt");
+    assert_eq!(
+        format!("{}:2\n{}:1\n", a.display(), b.display()),
+        String::from_utf8_lossy(&per_file.stdout)
+    );
+    assert_eq!(Some(0), total.status.code());
+    assert_eq!("3\n", String::from_utf8_lossy(&total.stdout));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that repeated `-p` patterns select the lines matching any of them, and highlight each.
+#[test]
+fn multiple_patterns() {
+    let input = "an error here\nall good\na warning there\nerror and warning\n";
+    let output = mgrep_stdin(
+        &["-p", "error", "--pattern=warning", "-n", "--color=always"],
+        input,
+    );
+
+    assert_eq!(
+        "\x1b[32m1\x1b[0m\x1b[36m:\x1b[0man \x1b[31merror\x1b[0m here\n\
+        \x1b[32m3\x1b[0m\x1b[36m:\x1b[0ma \x1b[31mwarning\x1b[0m there\n\
+        \x1b[32m4\x1b[0m\x1b[36m:\x1b[0m\x1b[31merror\x1b[0m and \x1b[31mwarning\x1b[0m\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = mgrep_stdin(&["-p", "error", "-p", ""], input);
+    assert_eq!(Some(2), output.status.code());
+}
+
+// Tests that `-o` with several patterns prints each match on its own line in the order they appear in the
+// line, whichever pattern they come from, and that overlapping matches are printed once.
+#[test]
+fn only_matching_patterns() {
+    let output = mgrep_stdin(
+        &["-p", "warning", "-p", "error", "-o", "-n"],
+        "error then warning, then error\n",
+    );
+    assert_eq!(
+        "1:error\n1:warning\n1:error\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    // `disk` and `full` overlap `disk full`, which is printed alone as the longest match starting first.
+    let output = mgrep_stdin(
+        &["-p", "full", "-p", "disk full", "-p", "disk", "-o"],
+        "error: disk full, disk\n",
+    );
+    assert_eq!("disk full\ndisk\n", String::from_utf8_lossy(&output.stdout));
+}
+
+// Tests that `-r` skips a hidden file by default, and searches it with `--hidden`.
+#[test]
+fn hidden_files() {
+    let dir = temp_dir("hidden_files");
+    fs::write(dir.join("visible.txt"), "token=public\n").unwrap();
+    fs::write(dir.join(".hidden"), "token=secret\n").unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let output = mgrep(&["token", dir_arg, "-r", "--no-filename"]);
+    assert_eq!("token=public\n", String::from_utf8_lossy(&output.stdout));
+
+    let output = mgrep(&[
+        "token",
+        dir_arg,
+        "-r",
+        "--no-filename",
+        "--hidden",
+        "--sort=path",
+    ]);
+    assert_eq!(
+        "token=secret\ntoken=public\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--patterns-from=-` reads the patterns from a pipe while searching a file, and that the text to
+// search can't come from stdin too.
+#[test]
+fn patterns_from_stdin() {
+    let dir = temp_dir("patterns_from_stdin");
+    let file = dir.join("app.log");
+    fs::write(&file, "info: started\nerror: disk full\nwarning: slow\n").unwrap();
+    let file_arg = file.to_str().unwrap();
+
+    for flag in [&["--patterns-from=-"][..], &["-f", "-"]] {
+        let mut args = flag.to_vec();
+        args.push(file_arg);
+        let output = mgrep_stdin(&args, "error\nwarning\n");
+        assert_eq!(
+            "error: disk full\nwarning: slow\n",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+
+    let output = mgrep_stdin(&["--patterns-from=-"], "error\n");
+    assert_eq!(Some(2), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("must be in a FILE"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--stats` prints the files searched and matched, and the matching lines, on stderr only.
+#[test]
+fn stats_summary() {
+    let dir = temp_dir("stats");
+    fs::write(dir.join("a.txt"), "fox\nfox again\n").unwrap();
+    fs::write(dir.join("b.txt"), "no match\n").unwrap();
+    fs::write(dir.join("c.txt"), "one fox\n").unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let plain = mgrep(&["fox", dir_arg, "-r", "--sort=path"]);
+    let output = mgrep(&["fox", dir_arg, "-r", "--sort=path", "--stats"]);
+
+    assert_eq!(plain.stdout, output.stdout);
+    assert_eq!(
+        "Matched 2 of 3 files, 3 total matches\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = mgrep(&["fox", dir_arg, "-r", "-q", "--stats"]);
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Matched "));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that a glob the shell didn't expand is expanded by mgrep, each match being prefixed by its file.
+#[test]
+fn unexpanded_glob() {
+    let dir = temp_dir("glob");
+    fs::write(dir.join("a.rs"), "fn main() {}\n").unwrap();
+    fs::write(dir.join("b.rs"), "// no function\nfn helper() {}\n").unwrap();
+    fs::write(dir.join("c.txt"), "fn in text\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mgrep"))
+        .args(["fn ", "*.rs", "--color=never"])
+        .current_dir(&dir)
+        .output()
+        .expect("failed to run mgrep");
+
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(
+        "a.rs:fn main() {}\nb.rs:fn helper() {}\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--mmap` finds the same lines as reading the files, that it transcodes UTF-16 files too, and
+// that files which can't be mapped (empty, or generated by the kernel) are still searched.
+#[test]
+fn memory_mapped_files() {
+    let dir = temp_dir("memory_mapped_files");
+    let text = dir.join("text.txt");
+    fs::write(&text, "fox\nno\nthe fox\n").unwrap();
+    let utf16 = dir.join("utf16.txt");
+    let mut bytes = vec![0xff, 0xfe];
+    bytes.extend("crème brûlée\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+    fs::write(&utf16, bytes).unwrap();
+    let empty = dir.join("empty.txt");
+    fs::write(&empty, "").unwrap();
+
+    let read = mgrep(&["fox", text.to_str().unwrap(), "-n"]);
+    let mapped = mgrep(&["fox", text.to_str().unwrap(), "-n", "--mmap"]);
+    assert_eq!(
+        "1:fox\n3:the fox\n",
+        String::from_utf8_lossy(&mapped.stdout)
+    );
+    assert_eq!(read.stdout, mapped.stdout);
+
+    let output = mgrep(&["brûlée", utf16.to_str().unwrap(), "--mmap", "--color=never"]);
+    assert_eq!("crème brûlée\n", String::from_utf8_lossy(&output.stdout));
+
+    let output = mgrep(&["fox", empty.to_str().unwrap(), "--mmap"]);
+    assert_eq!(Some(1), output.status.code());
+
+    if cfg!(target_os = "linux") {
+        let output = mgrep(&["Name", "/proc/self/status", "--mmap"]);
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("Name:"));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--pre` searches the output of the command run on each file instead of the file itself, and
+// that a failing command is reported for its file only.
+#[cfg(unix)]
+#[test]
+fn preprocessor() {
+    let dir = temp_dir("preprocessor");
+    fs::write(dir.join("a.txt"), "fox\nno\n").unwrap();
+    fs::write(dir.join("b.txt"), "the fox\n").unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let output = mgrep(&["fox", dir_arg, "-r", "--sort=path", "--pre", "cat"]);
+    let a = dir.join("a.txt").display().to_string();
+    let b = dir.join("b.txt").display().to_string();
+    assert_eq!(
+        format!("{a}:fox\n{b}:the fox\n"),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    // `false` prints nothing and fails, like a decompressor given a file in the wrong format.
+    let output = mgrep(&["fox", dir_arg, "-r", "--pre", "false"]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("mgrep: {a}: --pre command 'false' failed")));
+    assert!(stderr.contains(&format!("mgrep: {b}: --pre command 'false' failed")));
+
+    let output = mgrep(&["fox", &a, "--pre", "no-such-command"]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("can't run --pre command 'no-such-command'"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that a file that can't be read is reported on stderr by default and with `--report-errors`, that `-s`
+// silences it, the last of them winning, and that the other files are searched either way.
+#[cfg(unix)]
+#[test]
+fn unreadable_file_messages() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = temp_dir("unreadable_file_messages");
+    fs::write(dir.join("readable.txt"), "fox\n").unwrap();
+    let locked = dir.join("locked.txt");
+    fs::write(&locked, "fox\n").unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    // Root reads any file, so the preprocessor refuses it instead.
+    let refuse = dir.join("refuse.sh");
+    fs::write(
+        &refuse,
+        "#!/bin/sh\ncase \"$1\" in *locked*) echo 'Permission denied' >&2; exit 1;; esac\ncat\n",
+    )
+    .unwrap();
+    fs::set_permissions(&refuse, fs::Permissions::from_mode(0o755)).unwrap();
+    let pre = if fs::File::open(&locked).is_ok() {
+        vec!["--pre", refuse.to_str().unwrap()]
+    } else {
+        Vec::new()
+    };
+
+    let search = |flags: &[&str]| {
+        let mut args = vec!["fox", dir_arg, "-r", "--include=*.txt"];
+        args.extend(&pre);
+        args.extend(flags);
+        let output = mgrep(&args);
+        assert_eq!(Some(0), output.status.code());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("readable.txt:fox"));
+        String::from_utf8_lossy(&output.stderr).contains("locked.txt")
+    };
+
+    assert!(search(&[]));
+    assert!(search(&["--report-errors"]));
+    assert!(!search(&["-s"]));
+    assert!(!search(&["--report-errors", "--no-messages"]));
+    assert!(search(&["-s", "--report-errors"]));
+
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o644)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--search-zip` searches the decompressed lines of a gzip file, including those of a second
+// appended member, and that the compressed file is only reported as binary without it.
+#[test]
+fn gzip_files() {
+    use flate2::{write::GzEncoder, Compression};
+
+    let dir = temp_dir("gzip_files");
+    let gzip = |text: &str| {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    };
+    let log = dir.join("app.log.gz");
+    let mut bytes = gzip("started\nthe fox jumped\n");
+    bytes.extend(gzip("stopped\nanother fox\n"));
+    fs::write(&log, bytes).unwrap();
+    let log_arg = log.to_str().unwrap();
+
+    let output = mgrep(&["fox", log_arg, "-n", "--search-zip"]);
+    assert_eq!(
+        "2:the fox jumped\n4:another fox\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = mgrep(&["jumped", log_arg]);
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("the fox jumped"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `--timeout` stops a search stuck in a sleeping preprocessor with an error, long before it
+// would have finished.
+#[cfg(unix)]
+#[test]
+fn preprocessor_timeout() {
+    use std::{os::unix::fs::PermissionsExt, time::Instant};
+
+    let dir = temp_dir("preprocessor_timeout");
+    let file = dir.join("a.txt");
+    fs::write(&file, "fox\n").unwrap();
+    let sleeper = dir.join("sleeper.sh");
+    fs::write(&sleeper, "#!/bin/sh\nsleep 5\ncat\n").unwrap();
+    fs::set_permissions(&sleeper, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let start = Instant::now();
+    let output = mgrep(&[
+        "fox",
+        file.to_str().unwrap(),
+        "--pre",
+        sleeper.to_str().unwrap(),
+        "--timeout=300ms",
+    ]);
+    assert!(start.elapsed() < Duration::from_secs(4));
+    assert_eq!(Some(2), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("timed out after 300ms"));
+
+    let output = mgrep(&["fox", file.to_str().unwrap(), "--timeout=5h"]);
+    assert_eq!(Some(2), output.status.code());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `-H` prefixes the lines of a single file with its name, and that `--no-filename` removes the
+// prefix of many files, whichever of them comes last winning.
+#[test]
+fn filename_overrides() {
+    let dir = temp_dir("filename_overrides");
+    let a = dir.join("a.txt");
+    fs::write(&a, "fox\n").unwrap();
+    fs::write(dir.join("b.txt"), "the fox\n").unwrap();
+    let a_arg = a.to_str().unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let output = mgrep(&["fox", a_arg]);
+    assert_eq!("fox\n", String::from_utf8_lossy(&output.stdout));
+    let output = mgrep(&["fox", a_arg, "-H", "-n"]);
+    assert_eq!(
+        format!("{a_arg}:1:fox\n"),
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let output = mgrep(&["fox", a_arg, "--with-filename", "-c"]);
+    assert_eq!(
+        format!("{a_arg}:1\n"),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = mgrep(&["fox", dir_arg, "-r", "--sort=path", "--no-filename"]);
+    assert_eq!("fox\nthe fox\n", String::from_utf8_lossy(&output.stdout));
+    let output = mgrep(&["fox", a_arg, "--no-filename", "-H"]);
+    assert_eq!(
+        format!("{a_arg}:fox\n"),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that a directory given without `-r` is rejected with a hint, instead of the OS's `Is a directory`,
+// and that `-r` searches it.
+#[test]
+fn directory_without_recursive() {
+    let dir = temp_dir("directory");
+    fs::write(dir.join("notes.txt"), "a needle\n").unwrap();
+    let path = dir.to_str().unwrap();
+
+    for args in [&["needle", path][..], &["-c", "needle", path]] {
+        let output = mgrep(args);
+        assert_eq!(Some(2), output.status.code());
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+            "{path}: is a directory (use -r to search recursively)"
+        )));
+    }
+
+    let output = mgrep(&["-c", "needle", path, "-r"]);
+    assert_eq!(Some(0), output.status.code());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Tests that `-c` over several files leaves out the files without a match, unless `--include-zero` is given.
+#[test]
+fn zero_counts() {
+    let dir = temp_dir("zero_counts");
+    fs::write(dir.join("a.txt"), "fox\nfox fox\n").unwrap();
+    fs::write(dir.join("b.txt"), "no\n").unwrap();
+    fs::write(dir.join("c.txt"), "a fox\n").unwrap();
+    let dir_arg = dir.to_str().unwrap();
+    let (a, b, c) = (dir.join("a.txt"), dir.join("b.txt"), dir.join("c.txt"));
+
+    let output = mgrep(&["fox", dir_arg, "-r", "-c", "--sort=path"]);
+    assert_eq!(
+        format!("{}:2\n{}:1\n", a.display(), c.display()),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = mgrep(&["fox", dir_arg, "-r", "-c", "--sort=path", "--include-zero"]);
+    assert_eq!(
+        format!("{}:2\n{}:0\n{}:1\n", a.display(), b.display(), c.display()),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}